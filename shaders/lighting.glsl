@@ -0,0 +1,49 @@
+// Blinn-Phong in view space, MAX_LIGHTS is defined by the engine
+
+// kind: 0 directional, 1 point, 2 spot
+struct Light {
+    int kind;
+    vec3 position;
+    vec3 direction;
+    vec3 color;
+    vec3 attenuation;
+    float inner_cos;
+    float outer_cos;
+};
+
+uniform bool orthographic;
+uniform vec3 ambient;
+uniform int light_count;
+uniform Light lights[MAX_LIGHTS];
+
+// `shadow` scales the light at index `shadowed`, pass -1 to leave every light unshadowed
+vec3 shade(vec3 position, vec3 normal, vec3 albedo, vec3 specular, float shininess, int shadowed, float shadow) {
+    // Orthographic views have no eye point, every ray runs along +Z
+    vec3 to_eye = orthographic ? vec3(0.0, 0.0, -1.0) : normalize(-position);
+
+    vec3 result = ambient * albedo;
+    for (int i = 0; i < light_count; i++) {
+        Light light = lights[i];
+
+        vec3 to_light = -light.direction;
+        float strength = i == shadowed ? shadow : 1.0;
+        if (light.kind != 0) {
+            vec3 offset = light.position - position;
+            float distance = length(offset);
+            to_light = offset / distance;
+            strength *= 1.0 / dot(light.attenuation, vec3(1.0, distance, distance * distance));
+
+            if (light.kind == 2) {
+                float angle = dot(-to_light, light.direction);
+                strength *= smoothstep(light.outer_cos, light.inner_cos, angle);
+            }
+        }
+
+        float diffuse = max(dot(normal, to_light), 0.0);
+        vec3 half_vector = normalize(to_light + to_eye);
+        float highlight = diffuse > 0.0 ? pow(max(dot(normal, half_vector), 0.0), shininess) : 0.0;
+
+        result += (albedo * diffuse + specular * highlight) * light.color * strength;
+    }
+    return result;
+}
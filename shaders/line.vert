@@ -0,0 +1,15 @@
+#version 150
+
+// Unlit lines with per-vertex colors for the debug gizmos
+in vec3 position;
+in vec3 color;
+
+out vec3 v_color;
+
+uniform mat4 perspective;
+uniform mat4 view;
+
+void main() {
+    v_color = color;
+    gl_Position = perspective * view * vec4(position, 1.0);
+}
@@ -0,0 +1,62 @@
+#version 150
+
+#include "lighting.glsl"
+#include "shadow.glsl"
+
+in vec3 v_normal;
+in vec3 v_position;
+in vec2 v_tex_coords;
+out vec4 color;
+
+uniform vec4 base_color;
+uniform vec3 specular;
+uniform float shininess;
+
+// Unbound maps are skipped, so materials without textures look as before
+uniform sampler2D diffuse_map;
+uniform sampler2D specular_map;
+uniform sampler2D normal_map;
+uniform bool has_diffuse_map;
+uniform bool has_specular_map;
+uniform bool has_normal_map;
+
+// Tangent frame from screen-space derivatives, so meshes need no tangents.
+// The cross products are swapped compared to the usual version since view space is left-handed.
+mat3 cotangent_frame(vec3 normal, vec3 position, vec2 uv) {
+    vec3 dp1 = dFdx(position);
+    vec3 dp2 = dFdy(position);
+    vec2 duv1 = dFdx(uv);
+    vec2 duv2 = dFdy(uv);
+
+    vec3 dp2perp = cross(normal, dp2);
+    vec3 dp1perp = cross(dp1, normal);
+    vec3 tangent = dp2perp * duv1.x + dp1perp * duv2.x;
+    vec3 bitangent = dp2perp * duv1.y + dp1perp * duv2.y;
+
+    float scale = inversesqrt(max(max(dot(tangent, tangent), dot(bitangent, bitangent)), 1e-20));
+    return mat3(tangent * scale, bitangent * scale, normal);
+}
+
+void main() {
+    vec4 albedo = base_color;
+    if (has_diffuse_map) {
+        albedo *= texture(diffuse_map, v_tex_coords);
+    }
+
+    vec3 specular_color = specular;
+    if (has_specular_map) {
+        specular_color *= texture(specular_map, v_tex_coords).rgb;
+    }
+
+    vec3 normal = normalize(v_normal);
+    if (has_normal_map) {
+        vec3 mapped = texture(normal_map, v_tex_coords).xyz * 2.0 - 1.0;
+        normal = normalize(cotangent_frame(normal, v_position, v_tex_coords) * mapped);
+    }
+
+    // Geometric normal, so normal maps don't shift where the shadow starts
+    float shadow = get_shadow(v_position, normalize(v_normal));
+    int shadowed = has_shadow_map ? shadow_light : -1;
+    vec3 result = shade(v_position, normal, albedo.rgb, specular_color, shininess, shadowed, shadow);
+    color = vec4(result, albedo.a);
+}
@@ -0,0 +1,23 @@
+#version 150
+
+in vec3 position;
+in vec3 normal;
+in vec2 tex_coords;
+
+out vec3 v_normal;
+out vec3 v_position;
+out vec2 v_tex_coords;
+
+uniform mat4 perspective;
+uniform mat4 view;
+uniform mat4 model;
+
+void main() {
+    mat4 modelview = view * model;
+    vec4 position = modelview * vec4(position, 1.0);
+
+    v_normal = transpose(inverse(mat3(modelview))) * normal;
+    v_position = position.xyz;
+    v_tex_coords = tex_coords;
+    gl_Position = perspective * position;
+}
@@ -0,0 +1,5 @@
+#version 150
+
+// Shadow pass, only the depth buffer is written
+void main() {
+}
@@ -0,0 +1,37 @@
+// Shadow map lookup with PCF, needs lighting.glsl for the light direction
+
+uniform bool has_shadow_map;
+uniform sampler2DShadow shadow_map;
+// View space to shadow map coordinates and depth
+uniform mat4 shadow_matrix;
+uniform int shadow_light;
+uniform float shadow_bias;
+uniform int shadow_pcf_radius;
+
+// 1 where the shadow light reaches the point, 0 in full shadow
+float get_shadow(vec3 position, vec3 normal) {
+    if (!has_shadow_map) {
+        return 1.0;
+    }
+
+    vec3 coords = (shadow_matrix * vec4(position, 1.0)).xyz;
+    // Past the far side of the fitted box nothing can occlude
+    if (coords.z > 1.0) {
+        return 1.0;
+    }
+
+    // Surfaces turned away from the light need a larger offset
+    float facing = max(dot(normal, -lights[shadow_light].direction), 0.0);
+    float depth = coords.z - max(shadow_bias * (1.0 - facing), shadow_bias * 0.1);
+
+    vec2 texel = 1.0 / vec2(textureSize(shadow_map, 0));
+    float lit = 0.0;
+    for (int x = -shadow_pcf_radius; x <= shadow_pcf_radius; x++) {
+        for (int y = -shadow_pcf_radius; y <= shadow_pcf_radius; y++) {
+            lit += texture(shadow_map, vec3(coords.xy + vec2(x, y) * texel, depth));
+        }
+    }
+
+    float size = float(shadow_pcf_radius * 2 + 1);
+    return lit / (size * size);
+}
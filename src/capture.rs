@@ -0,0 +1,153 @@
+// Screenshots and numbered image sequences of the window
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use glium::backend::Facade;
+use glium::framebuffer::SimpleFrameBuffer;
+use glium::texture::{MipmapsOption, RawImage2d, Texture2d, UncompressedFloatFormat};
+use glium::uniforms::MagnifySamplerFilter;
+use glium::{BlitTarget, Surface};
+use image::RgbaImage;
+
+#[derive(Debug)]
+pub enum CaptureError {
+    // Copying the frame into a texture or reading that back failed
+    Read(String),
+    Io(PathBuf, io::Error),
+    Image(PathBuf, image::ImageError)
+}
+
+impl fmt::Display for CaptureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CaptureError::Read(err) => write!(f, "can't read the frame: {}", err),
+            CaptureError::Io(path, err) => write!(f, "can't create {}: {}", path.display(), err),
+            CaptureError::Image(path, err) => write!(f, "can't write {}: {}", path.display(), err)
+        }
+    }
+}
+
+impl std::error::Error for CaptureError {}
+
+// Frame being drawn, top row first. Has to be called before `finish`, which swaps the buffers.
+pub fn read_frame<F: Facade, S: Surface>(facade: &F, frame: &S) -> Result<RgbaImage, CaptureError> {
+    let read = |err: &dyn fmt::Display| CaptureError::Read(err.to_string());
+    let (width, height) = frame.get_dimensions();
+
+    // The back buffer can't be read directly, so it is copied into a texture first
+    let copy = Texture2d::empty_with_format(facade, UncompressedFloatFormat::U8U8U8U8, MipmapsOption::NoMipmap, width, height)
+        .map_err(|err| read(&err))?;
+    let target = SimpleFrameBuffer::new(facade, &copy).map_err(|err| read(&err))?;
+    let rect = BlitTarget {left: 0, bottom: 0, width: width as i32, height: height as i32};
+    frame.blit_whole_color_to(&target, &rect, MagnifySamplerFilter::Nearest);
+
+    let pixels: RawImage2d<u8> = copy.read();
+    let mut image = RgbaImage::from_raw(width, height, pixels.data.into_owned()).ok_or_else(|| read(&"unexpected pixel format"))?;
+    // The window's alpha is meaningless, keep the files opaque
+    for pixel in image.pixels_mut() {
+        pixel.0[3] = 255;
+    }
+    // GL rows start at the bottom
+    Ok(image::imageops::flip_vertical(&image))
+}
+
+// UTC time as "2026-01-31_23-59-59-999", sorts by time and is safe in file names
+pub fn get_timestamp() -> String {
+    let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_epoch.as_secs();
+    let (days, time) = (seconds / 86400, seconds % 86400);
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = match month_index < 10 {
+        true => month_index + 3,
+        false => month_index - 9
+    };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+
+    format!("{:04}-{:02}-{:02}_{:02}-{:02}-{:02}-{:03}", year, month, day,
+        time / 3600, time / 60 % 60, time % 60, since_epoch.subsec_millis())
+}
+
+fn save(image: &RgbaImage, path: &Path) -> Result<(), CaptureError> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|err| CaptureError::Io(dir.to_path_buf(), err))?;
+    }
+    image.save_with_format(path, image::ImageFormat::Png).map_err(|err| CaptureError::Image(path.to_path_buf(), err))
+}
+
+// Saves "screenshot_<timestamp>.png" into the folder and returns its path
+pub fn save_screenshot<P: AsRef<Path>>(image: &RgbaImage, dir: P) -> Result<PathBuf, CaptureError> {
+    let path = dir.as_ref().join(format!("screenshot_{}.png", get_timestamp()));
+    save(image, &path)?;
+    Ok(path)
+}
+
+// Writes every frame as "frame_00000.png", "frame_00001.png"... into its own folder.
+// While recording the engine advances the world by `get_timestep` per frame instead of
+// the real frame time, so the sequence plays back at `fps` however slow it was to render.
+pub struct Recorder {
+    dir: PathBuf,
+    fps: u32,
+    frames: u32
+}
+
+impl Recorder {
+    // Frames go into "recording_<timestamp>" inside `parent`
+    pub fn new<P: AsRef<Path>>(parent: P, fps: u32) -> Self {
+        let dir = parent.as_ref().join(format!("recording_{}", get_timestamp()));
+        Self {dir, fps: fps.max(1), frames: 0}
+    }
+
+    pub fn get_dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn get_timestep(&self) -> f32 {
+        1.0 / self.fps as f32
+    }
+
+    pub fn get_frame_count(&self) -> u32 {
+        self.frames
+    }
+
+    pub fn save(&mut self, image: &RgbaImage) -> Result<PathBuf, CaptureError> {
+        let path = self.dir.join(format!("frame_{:05}.png", self.frames));
+        save(image, &path)?;
+        self.frames += 1;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::headless::test_renderer;
+
+    #[test]
+    fn frames_are_read_top_row_first() {
+        let Some(headless) = test_renderer("frames_are_read_top_row_first") else {
+            return;
+        };
+
+        let color = Texture2d::empty_with_format(&headless, UncompressedFloatFormat::U8U8U8U8, MipmapsOption::NoMipmap, 4, 2).unwrap();
+        let mut frame = SimpleFrameBuffer::new(&headless, &color).unwrap();
+        frame.clear_color(0.0, 0.0, 1.0, 0.0);
+        // GL rects start at the bottom left
+        frame.clear(Some(&glium::Rect {left: 0, bottom: 0, width: 4, height: 1}), Some((1.0, 0.0, 0.0, 0.0)), false, None, None);
+
+        let image = read_frame(&headless, &frame).unwrap();
+        assert_eq!(image.dimensions(), (4, 2));
+        assert_eq!(image.get_pixel(0, 0).0, [0, 0, 255, 255]);
+        assert_eq!(image.get_pixel(3, 1).0, [255, 0, 0, 255]);
+    }
+}
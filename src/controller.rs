@@ -0,0 +1,373 @@
+// Camera controllers driven by the input state, updated once per frame
+use winit::event::{MouseButton, VirtualKeyCode};
+use winit::window::{CursorGrabMode, Window};
+
+use super::input::InputState;
+use super::math::{Aabb, Vec3};
+use super::{Camera, Projection};
+
+// First-person flight: WASD to move, E/Q up and down, Shift to speed up, mouse to look around
+pub struct FlyController {
+    // Radians per pixel of mouse movement
+    pub sensitivity: f32,
+    // Speed multiplier while Shift is held
+    pub fast_multiplier: f32,
+    // Mouse look only turns the camera while the cursor is grabbed
+    pub mouse_look: bool
+}
+
+impl Default for FlyController {
+    fn default() -> Self {
+        Self {sensitivity: 0.0025, fast_multiplier: 4.0, mouse_look: false}
+    }
+}
+
+impl FlyController {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // `delta` is the frame time in seconds, so movement doesn't depend on the frame rate
+    pub fn update(&self, camera: &mut Camera, input: &InputState, delta: f32) {
+        if self.mouse_look {
+            let (dx, dy) = input.get_mouse_delta();
+            camera.set_rotation(camera.yaw + dx * self.sensitivity, camera.pitch - dy * self.sensitivity);
+        }
+
+        let axis = |positive: VirtualKeyCode, negative: VirtualKeyCode| {
+            input.is_key_held(positive) as i32 as f32 - input.is_key_held(negative) as i32 as f32
+        };
+        let forward = axis(VirtualKeyCode::W, VirtualKeyCode::S);
+        let right = axis(VirtualKeyCode::D, VirtualKeyCode::A);
+        let up = axis(VirtualKeyCode::E, VirtualKeyCode::Q);
+
+        let movement = camera.get_direction() * forward + camera.get_right() * right + Vec3::Y * up;
+        if movement.length_squared() == 0.0 {
+            return;
+        }
+
+        let fast = input.is_key_held(VirtualKeyCode::LShift) || input.is_key_held(VirtualKeyCode::RShift);
+        let speed = match fast {
+            true => camera.get_speed() * self.fast_multiplier,
+            false => camera.get_speed()
+        };
+        // Diagonals aren't faster than straight lines
+        camera.position += movement.normalize() * speed * delta;
+    }
+}
+
+// Hides the cursor and keeps it in the window while grabbed. Returns whether it is grabbed now.
+pub fn grab_cursor(window: &Window, grab: bool) -> bool {
+    if !grab {
+        let _ = window.set_cursor_grab(CursorGrabMode::None);
+        window.set_cursor_visible(true);
+        return false;
+    }
+
+    // Platforms support only one of the two modes
+    let grabbed = window.set_cursor_grab(CursorGrabMode::Locked)
+        .or_else(|_| window.set_cursor_grab(CursorGrabMode::Confined));
+    match grabbed {
+        Ok(()) => {
+            window.set_cursor_visible(false);
+            true
+        },
+        Err(err) => {
+            println!("Can't grab the cursor: {}", err);
+            false
+        }
+    }
+}
+
+// Circles a target point for inspecting models: left drag rotates, middle drag pans, scrolling zooms.
+// The camera's yaw and pitch are the orbit angles, so switching from the fly camera keeps the view.
+pub struct OrbitController {
+    pub target: Vec3,
+    pub distance: f32,
+    pub min_distance: f32,
+    pub max_distance: f32,
+
+    // Radians per pixel of dragging
+    pub sensitivity: f32,
+    // Share of the distance moved per pixel of panning
+    pub pan_speed: f32,
+    // Share of the distance zoomed per scrolled line
+    pub zoom_speed: f32
+}
+
+impl Default for OrbitController {
+    fn default() -> Self {
+        Self {
+            target: Vec3::ZERO,
+            distance: 5.0,
+            min_distance: 0.1,
+            max_distance: 1000.0,
+            sensitivity: 0.005,
+            pan_speed: 0.0015,
+            zoom_speed: 0.1
+        }
+    }
+}
+
+impl OrbitController {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update(&mut self, camera: &mut Camera, input: &InputState) {
+        let (dx, dy) = input.get_mouse_delta();
+
+        // Dragging turns the model with the mouse, so the camera moves the other way
+        if input.is_button_held(MouseButton::Left) {
+            camera.set_rotation(camera.yaw + dx * self.sensitivity, camera.pitch - dy * self.sensitivity);
+        }
+
+        if input.is_button_held(MouseButton::Middle) {
+            let right = camera.get_right();
+            let up = camera.get_direction().cross(right);
+            // Grab and drag: the target moves against the mouse
+            self.target += (up * dy - right * dx) * self.pan_speed * self.distance;
+        }
+
+        let zoom = (1.0 - self.zoom_speed).powf(input.get_scroll());
+        self.distance = (self.distance * zoom).clamp(self.min_distance, self.max_distance);
+
+        self.apply(camera);
+    }
+
+    // Puts the camera `distance` away from the target, looking at it
+    pub fn apply(&self, camera: &mut Camera) {
+        camera.position = self.target - camera.get_direction() * self.distance;
+    }
+
+    // Orbits whatever the camera is looking at, `distance` ahead of it
+    pub fn follow(&mut self, camera: &Camera) {
+        self.target = camera.position + camera.get_direction() * self.distance;
+    }
+
+    // Centers the box and backs off until it fits the narrower of the camera's fields of view.
+    // Orthographic cameras are also sized to fit it. `aspect_ratio` is width / height.
+    pub fn frame(&mut self, camera: &mut Camera, bounds: Aabb, aspect_ratio: f32) {
+        let radius = (bounds.size().length() * 0.5).max(self.min_distance);
+        let half_height = camera.get_fov() * 0.5;
+        let half_width = (half_height.tan() * aspect_ratio).atan();
+
+        self.target = bounds.center();
+        self.distance = (radius / half_height.min(half_width).sin()).clamp(self.min_distance, self.max_distance);
+        if camera.is_orthographic() {
+            // `size` is the height, a view narrower than it is tall needs more of it
+            camera.set_projection(Projection::Orthographic {size: 2.0 * radius * (1.0 / aspect_ratio).max(1.0)});
+        }
+        self.apply(camera);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CameraMode {
+    Fly,
+    Orbit,
+    // The camera is left to the game's update callback
+    Fixed
+}
+
+// The controller for each mode and which one drives the camera
+pub struct CameraController {
+    mode: CameraMode,
+    pub fly: FlyController,
+    pub orbit: OrbitController
+}
+
+impl Default for CameraController {
+    fn default() -> Self {
+        Self {mode: CameraMode::Fly, fly: FlyController::new(), orbit: OrbitController::new()}
+    }
+}
+
+impl CameraController {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get_mode(&self) -> CameraMode {
+        self.mode
+    }
+
+    // Entering orbit mode orbits the point in front of the camera, so the view doesn't jump
+    pub fn set_mode(&mut self, mode: CameraMode, camera: &Camera) {
+        if mode == CameraMode::Orbit && self.mode != CameraMode::Orbit {
+            self.orbit.follow(camera);
+        }
+        self.mode = mode;
+    }
+
+    // Fly -> Orbit -> Fixed -> Fly
+    pub fn next_mode(&mut self, camera: &Camera) -> CameraMode {
+        let mode = match self.mode {
+            CameraMode::Fly => CameraMode::Orbit,
+            CameraMode::Orbit => CameraMode::Fixed,
+            CameraMode::Fixed => CameraMode::Fly
+        };
+        self.set_mode(mode, camera);
+        mode
+    }
+
+    // Switches to orbiting around the box
+    pub fn frame(&mut self, camera: &mut Camera, bounds: Aabb, aspect_ratio: f32) {
+        self.set_mode(CameraMode::Orbit, camera);
+        self.orbit.frame(camera, bounds, aspect_ratio);
+    }
+
+    pub fn update(&mut self, camera: &mut Camera, input: &InputState, delta: f32) {
+        match self.mode {
+            CameraMode::Fly => self.fly.update(camera, input, delta),
+            CameraMode::Orbit => self.orbit.update(camera, input),
+            CameraMode::Fixed => ()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: Vec3, expected: Vec3) {
+        assert!((actual - expected).length() < 1e-4, "{:?} != {:?}", actual, expected);
+    }
+
+    // Where the camera ends up after holding `keys` for `steps` frames of `delta` seconds
+    fn fly(keys: &[VirtualKeyCode], steps: usize, delta: f32) -> Camera {
+        let mut input = InputState::new();
+        for key in keys {
+            input.hold_key(*key);
+        }
+        let mut camera = Camera::new();
+        for _ in 0..steps {
+            FlyController::new().update(&mut camera, &input, delta);
+        }
+        camera
+    }
+
+    #[test]
+    fn mouse_look_stops_at_the_pitch_limit() {
+        let fly = FlyController {mouse_look: true, ..FlyController::new()};
+        let mut camera = Camera::new();
+
+        // Mouse y grows downwards
+        let mut input = InputState::new();
+        input.move_mouse(10.0, -1e6);
+        fly.update(&mut camera, &input, 0.016);
+        assert_eq!(camera.pitch, Camera::MAX_PITCH);
+        assert_eq!(camera.yaw, 10.0 * fly.sensitivity);
+
+        input.end_frame();
+        input.move_mouse(0.0, 2e6);
+        fly.update(&mut camera, &input, 0.016);
+        assert_eq!(camera.pitch, -Camera::MAX_PITCH);
+    }
+
+    #[test]
+    fn movement_follows_the_frame_time() {
+        let once = fly(&[VirtualKeyCode::W, VirtualKeyCode::D], 1, 0.2);
+        let twice = fly(&[VirtualKeyCode::W, VirtualKeyCode::D], 2, 0.1);
+        assert_close(once.position, twice.position);
+        assert!((once.position.length() - 0.2 * Camera::new().get_speed()).abs() < 1e-5);
+    }
+
+    #[test]
+    fn shift_speeds_up() {
+        let speed = Camera::new().get_speed();
+        assert_close(fly(&[VirtualKeyCode::W], 1, 1.0).position, Vec3::Z * speed);
+
+        let multiplier = FlyController::new().fast_multiplier;
+        assert_close(fly(&[VirtualKeyCode::W, VirtualKeyCode::LShift], 1, 1.0).position, Vec3::Z * speed * multiplier);
+        assert_close(fly(&[VirtualKeyCode::W, VirtualKeyCode::RShift], 1, 1.0).position, Vec3::Z * speed * multiplier);
+    }
+
+    #[test]
+    fn e_and_q_move_along_world_y() {
+        let mut input = InputState::new();
+        input.hold_key(VirtualKeyCode::E);
+        let mut camera = Camera::new();
+        // Looking down and to the side doesn't tilt vertical movement
+        camera.set_rotation(0.7, -1.0);
+        FlyController::new().update(&mut camera, &input, 1.0);
+        assert_close(camera.position, Vec3::Y * camera.get_speed());
+
+        assert_close(fly(&[VirtualKeyCode::Q], 1, 1.0).position, -Vec3::Y * Camera::new().get_speed());
+        assert_close(fly(&[VirtualKeyCode::E, VirtualKeyCode::Q], 1, 1.0).position, Vec3::ZERO);
+    }
+
+    #[test]
+    fn dragging_orbits_the_target() {
+        let mut orbit = OrbitController {target: Vec3::new(1.0, 2.0, 3.0), ..OrbitController::new()};
+        let mut camera = Camera::new();
+        let mut input = InputState::new();
+        input.hold_button(MouseButton::Left);
+        input.move_mouse(100.0, -50.0);
+        orbit.update(&mut camera, &input);
+
+        assert!((camera.yaw - 100.0 * orbit.sensitivity).abs() < 1e-6);
+        assert!((camera.pitch - 50.0 * orbit.sensitivity).abs() < 1e-6);
+        // Still looking at the target from the same distance
+        assert_close(camera.position + camera.get_direction() * orbit.distance, orbit.target);
+        assert!(((camera.position - orbit.target).length() - orbit.distance).abs() < 1e-4);
+    }
+
+    #[test]
+    fn panning_drags_the_target() {
+        let mut orbit = OrbitController::new();
+        let mut camera = Camera::new();
+        camera.set_rotation(0.5, 0.0);
+        let mut input = InputState::new();
+        input.hold_button(MouseButton::Middle);
+        input.move_mouse(10.0, 0.0);
+        orbit.update(&mut camera, &input);
+
+        // Moving the mouse right pulls the scene right, the target goes left
+        assert_close(orbit.target, -camera.get_right() * 10.0 * orbit.pan_speed * 5.0);
+        assert_eq!(camera.yaw, 0.5);
+        assert_close(camera.position + camera.get_direction() * orbit.distance, orbit.target);
+    }
+
+    #[test]
+    fn zooming_stays_within_the_limits() {
+        let mut orbit = OrbitController::new();
+        let mut camera = Camera::new();
+        let mut input = InputState::new();
+
+        input.scroll(1.0);
+        orbit.update(&mut camera, &input);
+        assert!((orbit.distance - 5.0 * (1.0 - orbit.zoom_speed)).abs() < 1e-5);
+
+        input.scroll(1000.0);
+        orbit.update(&mut camera, &input);
+        assert_eq!(orbit.distance, orbit.min_distance);
+
+        input.end_frame();
+        input.scroll(-1000.0);
+        orbit.update(&mut camera, &input);
+        assert_eq!(orbit.distance, orbit.max_distance);
+    }
+
+    #[test]
+    fn framing_fits_the_bounds_in_view() {
+        let bounds = Aabb::new(Vec3::new(-1.0, -2.0, 0.0), Vec3::new(3.0, 1.0, 2.0));
+
+        for projection in [Projection::Perspective, Projection::Orthographic {size: 1.0}] {
+            // Narrow views have to back off further than the vertical field of view suggests
+            for aspect_ratio in [0.3, 1.0, 2.5] {
+                let mut orbit = OrbitController::new();
+                let mut camera = Camera::new();
+                camera.set_projection(projection);
+                camera.set_rotation(0.7, -0.4);
+                orbit.frame(&mut camera, bounds, aspect_ratio);
+
+                assert_close(orbit.target, bounds.center());
+                let frustum = camera.get_frustum(aspect_ratio);
+                for corner in bounds.get_corners() {
+                    assert!(frustum.contains_point(corner), "{:?} at {} cuts {:?}", projection, aspect_ratio, corner);
+                }
+            }
+        }
+    }
+}
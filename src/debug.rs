@@ -0,0 +1,163 @@
+// Debug gizmos: world axes, ground grid, object bounds and user queued lines
+use glium::{Rect, Surface};
+
+use super::math::{Aabb, Mat4, Vec3};
+use super::renderer::Renderer;
+use super::World;
+
+#[derive(Copy, Clone)]
+pub struct DebugVertex {
+    position: [f32; 3],
+    color: [f32; 3]
+}
+
+glium::implement_vertex!(DebugVertex, position, color);
+
+pub struct DebugDraw {
+    pub enabled: bool,
+    pub axes: bool,
+    pub grid: bool,
+    pub bounds: bool,
+    // Cells from the origin to the edge of the grid
+    pub grid_size: u32,
+
+    // Line list, cleared after every frame
+    lines: Vec<DebugVertex>
+}
+
+impl Default for DebugDraw {
+    fn default() -> Self {
+        Self {enabled: true, axes: true, grid: true, bounds: false, grid_size: 10, lines: Vec::new()}
+    }
+}
+
+impl DebugDraw {
+    pub fn line(&mut self, a: Vec3, b: Vec3, color: Vec3) {
+        let color = color.get_matrix();
+        self.lines.push(DebugVertex {position: a.get_matrix(), color});
+        self.lines.push(DebugVertex {position: b.get_matrix(), color});
+    }
+
+    // Small cross, `size` across
+    pub fn point(&mut self, position: Vec3, size: f32, color: Vec3) {
+        let half = size * 0.5;
+        for axis in [Vec3::X, Vec3::Y, Vec3::Z] {
+            self.line(position - axis * half, position + axis * half, color);
+        }
+    }
+
+    // The 12 edges of the box moved by `matrix`
+    pub fn aabb(&mut self, aabb: Aabb, matrix: Mat4, color: Vec3) {
+        let corners = aabb.get_corners().map(|corner| matrix.transform_point(corner));
+
+        for i in 0..8 {
+            for bit in [1, 2, 4] {
+                if i & bit == 0 {
+                    self.line(corners[i], corners[i | bit], color);
+                }
+            }
+        }
+    }
+
+    pub fn get_lines(&self) -> &[DebugVertex] {
+        &self.lines
+    }
+
+    pub fn clear(&mut self) {
+        self.lines.clear();
+    }
+
+    fn add_axes(&mut self, length: f32) {
+        self.line(Vec3::ZERO, Vec3::X * length, Vec3::new(1.0, 0.0, 0.0));
+        self.line(Vec3::ZERO, Vec3::Y * length, Vec3::new(0.0, 1.0, 0.0));
+        self.line(Vec3::ZERO, Vec3::Z * length, Vec3::new(0.0, 0.0, 1.0));
+    }
+
+    // Grid on the XZ plane
+    fn add_grid(&mut self) {
+        let size = self.grid_size as f32;
+        let color = Vec3::splat(0.4);
+
+        for i in -(self.grid_size as i32)..=self.grid_size as i32 {
+            let i = i as f32;
+            self.line(Vec3::new(i, 0.0, -size), Vec3::new(i, 0.0, size), color);
+            self.line(Vec3::new(-size, 0.0, i), Vec3::new(size, 0.0, i), color);
+        }
+    }
+}
+
+impl World {
+    pub fn debug_line(&mut self, a: Vec3, b: Vec3, color: Vec3) {
+        self.debug.line(a, b, color);
+    }
+
+    pub fn debug_point(&mut self, position: Vec3, color: Vec3) {
+        self.debug.point(position, 0.1, color);
+    }
+
+    pub fn get_debug(&self) -> &DebugDraw {
+        &self.debug
+    }
+
+    pub fn get_debug_mut(&mut self) -> &mut DebugDraw {
+        &mut self.debug
+    }
+
+    // Draws the gizmos and everything queued since the last frame, then empties the queue
+    pub(super) fn draw_axis<S: Surface>(&mut self, frame: &mut S, renderer: &mut Renderer, rect: Option<Rect>) {
+        if !self.debug.enabled {
+            self.debug.clear();
+            return;
+        }
+
+        // Axes go first so they win the depth test against the grid lines under them
+        if self.debug.axes {
+            self.debug.add_axes(self.debug.grid_size.max(1) as f32);
+        }
+        if self.debug.grid {
+            self.debug.add_grid();
+        }
+
+        if self.debug.bounds {
+            self.update_transforms();
+
+            let boxes: Vec<(Aabb, Mat4)> = self.get_nodes().filter_map(|(_, node)| {
+                node.get_mesh().map(|mesh| (self.meshes.get_bounds(mesh), node.get_world_matrix()))
+            }).collect();
+            for (aabb, matrix) in boxes {
+                self.debug.aabb(aabb, matrix, Vec3::new(1.0, 1.0, 0.0));
+            }
+        }
+
+        // Selection is outlined even with the other boxes hidden
+        if let Some(bounds) = self.get_selected().and_then(|id| self.get_bounds(id)) {
+            self.debug.aabb(bounds, Mat4::IDENTITY, Vec3::new(0.2, 0.8, 1.0));
+        }
+
+        // Lines are neither shadowed nor casting, so no batches are needed
+        let uniforms = self.get_frame_uniforms(frame.get_dimensions(), None, rect);
+        renderer.draw_lines(frame, self.debug.get_lines(), &uniforms);
+        self.debug.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queued_shapes_become_lines() {
+        let mut world = World::new("Debug");
+        world.debug_line(Vec3::ZERO, Vec3::X, Vec3::ONE);
+        world.debug_point(Vec3::ONE, Vec3::ONE);
+        // One line, then one per axis of the cross
+        assert_eq!(world.get_debug().get_lines().len(), 2 * 4);
+        assert_eq!(world.get_debug().get_lines()[1].position, [1.0, 0.0, 0.0]);
+
+        world.get_debug_mut().aabb(Aabb::new(Vec3::ZERO, Vec3::ONE), Mat4::IDENTITY, Vec3::ONE);
+        assert_eq!(world.get_debug().get_lines().len(), 2 * (4 + 12));
+
+        world.get_debug_mut().clear();
+        assert!(world.get_debug().get_lines().is_empty());
+    }
+}
@@ -1,8 +1,5 @@
-use glium::DrawParameters;
 // Open GL Wrapper
-use glium::glutin::surface::WindowSurface;
-use glium::index::NoIndices;
-use glium::{Frame, Surface, Display, backend::glutin::SimpleWindowBuilder, uniform};
+use glium::{Rect, Surface, backend::glutin::SimpleWindowBuilder};
 
 extern crate typetag;
 use serde::{Serialize, Deserialize};
@@ -10,118 +7,148 @@ use serde::{Serialize, Deserialize};
 use winit::dpi::PhysicalSize;
 use winit::event::VirtualKeyCode;
 use winit::window::Fullscreen;
-use winit::{window, event_loop};
 use winit::{
     event::Event,
     event::WindowEvent,
     event_loop::ControlFlow,
     event_loop::EventLoop
 };
+use image::RgbaImage;
 
-use std::fs;
-use std::fs::File;
-use std::io::prelude::*;
-use std::f32::consts::PI;
-use std::process::Output;
+use std::any::Any;
+use std::collections::HashMap;
+use std::path::PathBuf;
 use std::time::Instant;
 
 #[path ="../src/input.rs"]
 mod input;
-use input::Key;
+use input::{InputState, Key};
 
 #[path ="../src/teapot.rs"]
 mod teapot;
 
-#[derive(Copy, Clone)]
-pub struct Vertex {
-    position: (f32, f32, f32),
-    normal: (f32, f32, f32)
-}
+#[path ="../src/math.rs"]
+pub mod math;
+pub use math::{Vec3, Mat4, Quat, radians};
+use math::Frustum;
 
-glium::implement_vertex!(Vertex, position, normal);
+#[path ="../src/transform.rs"]
+pub mod transform;
+pub use transform::Transform;
 
-#[derive(Clone, Copy, Default, Serialize, Deserialize)]
-pub struct Vec3 {
-    x: f32,
-    y: f32,
-    z: f32
-}
+#[path ="../src/scene.rs"]
+pub mod scene;
+use scene::{NodeId, Slot};
 
-impl Vec3 {
-    pub fn new(x: f32, y: f32, z: f32) -> Self {
-        Self {x, y, z}
-    }
+#[path ="../src/mesh.rs"]
+pub mod mesh;
+use mesh::{Mesh, MeshLibrary};
 
-    pub fn get_tuple(&self) -> (f32, f32, f32) {
-        (self.x, self.y, self.z)
-    }
+#[path ="../src/primitives.rs"]
+pub mod primitives;
 
-    pub fn get_matrix(&self) -> [f32; 3] {
-        [self.x, self.y, self.z]
-    }
+#[path ="../src/material.rs"]
+pub mod material;
+pub use material::Material;
 
-    pub fn from_matrix(matrix: [f32; 3]) -> Self {
-        Self {x: matrix[0], y: matrix[1], z: matrix[2]}
-    }
+#[path ="../src/texture.rs"]
+pub mod texture;
 
-    pub fn mul_f32(&self, a: f32) -> Self {
-        let x = self.x * a;
-        let y = self.y * a;
-        let z = self.z * a;
+#[path ="../src/light.rs"]
+pub mod light;
 
-        Self {x, y, z}
-    }
+#[path ="../src/shadow.rs"]
+pub mod shadow;
+use shadow::{ShadowData, ShadowSettings};
 
-    pub fn sin(&self) -> Self {
-        let x = self.x.sin();
-        let y = self.y.sin();
-        let z = self.z.sin();
+#[path ="../src/shader.rs"]
+pub mod shader;
+use shader::default_shader_dir;
 
-        Self { x, y, z }
-    }
-}
+#[path ="../src/renderer.rs"]
+pub mod renderer;
+use renderer::{Batch, FrameTimes, FrameUniforms, Renderer};
 
-impl std::ops::Mul for Vec3 {
-    type Output = Self;
+#[path ="../src/obj.rs"]
+pub mod obj;
 
-    fn mul(self, rhs: Self) -> Self::Output {
-        let x = self.x * rhs.x;
-        let y = self.y * rhs.y;
-        let z = self.z * rhs.z;
+#[path ="../src/gltf_import.rs"]
+pub mod gltf_import;
 
-        Self {x, y, z}
-    }
+#[path ="../src/debug.rs"]
+pub mod debug;
+use debug::DebugDraw;
+
+#[path ="../src/headless.rs"]
+pub mod headless;
+
+#[path ="../src/controller.rs"]
+pub mod controller;
+use controller::{CameraController, CameraMode};
+
+#[path ="../src/view.rs"]
+pub mod view;
+use view::Viewport;
+
+#[path ="../src/capture.rs"]
+pub mod capture;
+use capture::Recorder;
+
+#[path ="../src/save.rs"]
+pub mod save;
+
+#[derive(Copy, Clone)]
+pub struct Vertex {
+    position: (f32, f32, f32),
+    normal: (f32, f32, f32),
+    tex_coords: (f32, f32)
 }
 
-impl std::ops::AddAssign for Vec3 {
-    fn add_assign(&mut self, rhs: Self) {
-        self.x += rhs.x;
-        self.y += rhs.y;
-        self.z += rhs.z;   
-    }
+glium::implement_vertex!(Vertex, position, normal, tex_coords);
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Projection {
+    // Uses the camera's field of view
+    Perspective,
+    // `size` is the visible height in world units, the width follows the aspect ratio
+    Orthographic {size: f32}
 }
 
+// Yaw 0 looks along +Z and grows towards +X, pitch is positive upwards (radians)
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Camera {
     pub position: Vec3,
-    pub direction: Vec3,
+    pub yaw: f32,
+    pub pitch: f32,
 
     fov: f32,
-    speed: f32
-}
-
-pub fn radians(x: f32) -> f32 {
-    x * PI / 180.0
+    // Movement in units per second
+    speed: f32,
+
+    projection: Projection,
+    znear: f32,
+    zfar: f32,
+    // Perspective only: ignores zfar and never clips distant objects
+    infinite_far: bool,
+    // Depth in [0; 1] with near at 1 and far at 0, the depth test and clear value flip with it
+    reverse_z: bool
 }
 
 impl Camera {
+    // Keeps the view from flipping over when looking straight up or down
+    pub const MAX_PITCH: f32 = 89.0 * std::f32::consts::PI / 180.0;
+
     pub fn new() -> Self {
         let position = Vec3::default();
-        let direction = Vec3::default();
 
-        let fov = 60.0 * PI / 180.0;
-        let speed = 1.0/100.0;
+        let fov = radians(60.0);
+        let speed = 3.0;
 
-        Self {position, direction, fov, speed}
+        let znear = 0.1;
+        let zfar = 1000.0;
+
+        Self {position, yaw: 0.0, pitch: 0.0, fov, speed, projection: Projection::Perspective, znear, zfar, infinite_far: false, reverse_z: false}
     }
 
     // Degrees -> Radians
@@ -133,219 +160,526 @@ impl Camera {
         self.fov
     }
 
-    pub fn get_perspective(&self, frame: &Frame) -> [[f32; 4]; 4] {
-        let aspect_ratio = frame.get_dimensions().0 as f32 / frame.get_dimensions().1 as f32;
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed;
+    }
 
-        let fov = self.fov;
-        let zfar = 64.0;
-        let znear = 0.1;
+    pub fn get_speed(&self) -> f32 {
+        self.speed
+    }
 
-        let f = 1.0 / (fov / 2.0).tan();
+    // Pitch is clamped to MAX_PITCH
+    pub fn set_rotation(&mut self, yaw: f32, pitch: f32) {
+        self.yaw = yaw;
+        self.pitch = pitch.clamp(-Self::MAX_PITCH, Self::MAX_PITCH);
+    }
 
-        [
-            [  f / aspect_ratio   ,   0.0,               0.0              ,   0.0],
-            [         0.0         ,     f ,              0.0              ,   0.0],
-            [         0.0         ,    0.0,  (zfar+znear)/(zfar-znear)    ,   1.0],
-            [         0.0         ,    0.0, -(2.0*zfar*znear)/(zfar-znear),   0.0],
-        ]
+    // Unit vector the camera looks along
+    pub fn get_direction(&self) -> Vec3 {
+        let (sin_yaw, cos_yaw) = self.yaw.sin_cos();
+        let (sin_pitch, cos_pitch) = self.pitch.sin_cos();
+        Vec3::new(cos_pitch * sin_yaw, sin_pitch, cos_pitch * cos_yaw)
     }
 
-    pub fn get_view(&self) -> [[f32; 4]; 4] {
-        let f = {
-            let d = self.direction.get_tuple();
-            let len = d.0 * d.0 + d.1 * d.1 + d.2 * d.2;
-            let len = len.sqrt();
-            (d.0 / len, d.1 / len, d.2 / len)
-        };
+    pub fn set_direction(&mut self, direction: Vec3) {
+        let direction = direction.normalize();
+        self.set_rotation(direction.x.atan2(direction.z), direction.y.clamp(-1.0, 1.0).asin());
+    }
 
-        let up = (0.0, 1.0, 0.0);
+    pub fn look_at(&mut self, target: Vec3) {
+        self.set_direction(target - self.position);
+    }
 
-        let s = (f.1 * up.2 - f.2 * up.1,
-                 f.2 * up.0 - f.0 * up.2,
-                 f.0 * up.1 - f.1 * up.0);
+    // Horizontal unit vector pointing to the right of the view
+    pub fn get_right(&self) -> Vec3 {
+        let (sin_yaw, cos_yaw) = self.yaw.sin_cos();
+        Vec3::new(cos_yaw, 0.0, -sin_yaw)
+    }
 
-        let s_norm = {
-            let len = s.0 * s.0 + s.1 * s.1 + s.2 * s.2;
-            let len = len.sqrt();
-            (s.0 / len, s.1 / len, s.2 / len)
-        };
+    pub fn set_projection(&mut self, projection: Projection) {
+        self.projection = projection;
+    }
+
+    pub fn get_projection(&self) -> Projection {
+        self.projection
+    }
+
+    pub fn is_orthographic(&self) -> bool {
+        matches!(self.projection, Projection::Orthographic {..})
+    }
+
+    // Distances along the view direction, 0 < znear < zfar. A smaller znear costs depth precision everywhere.
+    pub fn set_clip_planes(&mut self, znear: f32, zfar: f32) {
+        self.znear = znear;
+        self.zfar = zfar;
+    }
+
+    pub fn get_clip_planes(&self) -> (f32, f32) {
+        (self.znear, self.zfar)
+    }
+
+    pub fn set_infinite_far(&mut self, infinite_far: bool) {
+        self.infinite_far = infinite_far;
+    }
+
+    pub fn is_infinite_far(&self) -> bool {
+        self.infinite_far
+    }
+
+    // Spreads depth precision evenly over distance, mostly useful with a far away or infinite zfar
+    pub fn set_reverse_z(&mut self, reverse_z: bool) {
+        self.reverse_z = reverse_z;
+    }
+
+    pub fn is_reverse_z(&self) -> bool {
+        self.reverse_z
+    }
+
+    // Depth buffer value of the far plane, what the frame is cleared to
+    pub fn get_clear_depth(&self) -> f32 {
+        match self.reverse_z {
+            true => 0.0,
+            false => 1.0
+        }
+    }
+
+    // Every draw path projects with this, `aspect_ratio` is width / height.
+    // With reverse-Z the result is meant for `ClipControlDepth::ZeroToOne`, see Renderer.
+    pub fn projection_matrix(&self, aspect_ratio: f32) -> Mat4 {
+        let projection = self.get_gl_projection(aspect_ratio);
+        match self.reverse_z {
+            // Depth in [-1; 1] to 1 at the near plane and 0 at the far one, where floats are
+            // densest near 0 and make up for the perspective divide bunching distant depths
+            true => Mat4::translation(Vec3::new(0.0, 0.0, 0.5)) * Mat4::scale(Vec3::new(1.0, 1.0, -0.5)) * projection,
+            false => projection
+        }
+    }
 
-        let u = (s_norm.1 * f.2 - s_norm.2 * f.1,
-                 s_norm.2 * f.0 - s_norm.0 * f.2,
-                 s_norm.0 * f.1 - s_norm.1 * f.0);
+    // Projection with depth in [-1; 1], reverse-Z or not
+    fn get_gl_projection(&self, aspect_ratio: f32) -> Mat4 {
+        match self.projection {
+            Projection::Perspective => match self.infinite_far {
+                true => Mat4::perspective_infinite(self.fov, aspect_ratio, self.znear),
+                false => Mat4::perspective(self.fov, aspect_ratio, self.znear, self.zfar)
+            },
+            Projection::Orthographic {size} => {
+                let (half_width, half_height) = (size * aspect_ratio * 0.5, size * 0.5);
+                Mat4::orthographic(-half_width, half_width, -half_height, half_height, self.znear, self.zfar)
+            }
+        }
+    }
+
+    // What the camera sees, objects entirely outside it can be skipped
+    pub fn get_frustum(&self, aspect_ratio: f32) -> Frustum {
+        // The planes are extracted for depth in [-1; 1]
+        Frustum::from_matrix(self.get_gl_projection(aspect_ratio) * self.get_view_matrix())
+    }
+
+    // Projection for the frame's aspect ratio
+    pub fn get_projection_matrix<S: Surface>(&self, frame: &S) -> Mat4 {
+        let (width, height) = frame.get_dimensions();
+        self.projection_matrix(width as f32 / height.max(1) as f32)
+    }
 
-        let position = self.position.get_tuple();
-        
-        let p = (-position.0 * s.0 - position.1 * s.1 - position.2 * s.2,
-                 -position.0 * u.0 - position.1 * u.1 - position.2 * u.2,
-                 -position.0 * f.0 - position.1 * f.1 - position.2 * f.2);
+    pub fn get_view(&self) -> [[f32; 4]; 4] {
+        self.get_view_matrix().get_matrix()
+    }
+
+    pub fn get_view_matrix(&self) -> Mat4 {
+        Mat4::look_to(self.position, self.get_direction(), Vec3::Y)
+    }
+}
 
-        [
-            [s_norm.0, u.0, f.0, 0.0],
-            [s_norm.1, u.1, f.1, 0.0],
-            [s_norm.2, u.2, f.2, 0.0],
-            [p.0, p.1,  p.2, 1.0],
-        ]
+impl Default for Camera {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 // World 
 #[derive(Default)]
 pub struct World {
-    pub name: &'static str,
-    objects: Vec<&'static mut dyn Object>,
+    pub name: String,
+    pub camera: Camera,
+    nodes: Vec<Slot>,
+    free_slots: Vec<u32>,
+    meshes: MeshLibrary,
+    // Images without a file of their own, like the ones embedded in glTF models
+    images: HashMap<String, RgbaImage>,
+    // Towards the light, see `set_global_light`
     global_light: Vec3,
-
-    ambient_color: (f32, f32, f32, f32)
+    global_light_color: Vec3,
+
+    ambient_color: (f32, f32, f32, f32),
+    shadows: ShadowSettings,
+    // Where the main camera draws, scene cameras bring their own
+    viewport: Viewport,
+    selected: Option<NodeId>,
+    debug: DebugDraw
 }
 
 impl World {
-    pub fn new(name: &'static str) -> &'static mut Self {
+    pub fn new(name: &str) -> Self {
+        let name = name.to_string();
         let global_light = Vec3::new(-1.0, 0.4, 0.9);
-        Box::leak(Box::new(Self {name, global_light, ..Default::default()}))
+        Self {name, global_light, global_light_color: Vec3::ONE, ..Default::default()}
     }
 
-    fn draw_axis(&self, frame: &mut Frame, camera: &Camera, display: &glium::Display<WindowSurface>) {
-        let perspective = camera.get_perspective(frame);
+    // Groups drawable nodes by mesh, material and shadow flags. Batches come out sorted by material
+    // (in order of first appearance) so equal materials are bound back to back.
+    pub fn collect_batches(&mut self) -> Vec<Batch> {
+        self.update_transforms();
+
+        let default_material = Material::default();
+        let mut materials: Vec<&Material> = Vec::new();
+        // Paired with the index of the batch's material
+        let mut batches: Vec<(usize, Batch)> = Vec::new();
+
+        for (_, node) in self.get_nodes() {
+            let Some(mesh) = node.get_mesh() else {
+                continue;
+            };
+
+            let material = node.get_object().material().unwrap_or(&default_material);
+            let material_index = match materials.iter().position(|other| *other == material) {
+                Some(index) => index,
+                None => {
+                    materials.push(material);
+                    materials.len() - 1
+                }
+            };
+
+            let (cast_shadows, receive_shadows) = (node.casts_shadows(), node.receives_shadows());
+            let index = match batches.iter().position(|(other, batch)| *other == material_index && batch.mesh == mesh
+                && batch.cast_shadows == cast_shadows && batch.receive_shadows == receive_shadows) {
+                Some(index) => index,
+                None => {
+                    let batch = Batch {mesh, material: material.clone(), models: Vec::new(), cast_shadows, receive_shadows};
+                    batches.push((material_index, batch));
+                    batches.len() - 1
+                }
+            };
+            batches[index].1.models.push(node.get_world_matrix());
+        }
+
+        // Stable, so batches of one material keep their order
+        batches.sort_by_key(|(material, _)| *material);
+        batches.into_iter().map(|(_, batch)| batch).collect()
+    }
 
+    // Lights come from world matrices, so transforms must be up to date
+    // `rect` is the part of the frame drawn into, all of its `dimensions` when None
+    fn get_frame_uniforms(&self, dimensions: (u32, u32), shadow: Option<ShadowData>, rect: Option<Rect>) -> FrameUniforms {
+        let view = self.camera.get_view_matrix();
+        let (r, g, b, _) = self.ambient_color;
+        let (width, height) = rect.map_or(dimensions, |rect| (rect.width, rect.height));
+        let aspect_ratio = width as f32 / height.max(1) as f32;
+
+        FrameUniforms {
+            perspective: self.camera.projection_matrix(aspect_ratio).get_matrix(),
+            // Not from the projection above, its depth may be reversed
+            frustum: self.camera.get_frustum(aspect_ratio),
+            orthographic: self.camera.is_orthographic(),
+            reverse_z: self.camera.is_reverse_z(),
+            viewport: rect,
+            view: view.get_matrix(),
+            ambient: [r, g, b],
+            lights: self.collect_lights(view),
+            shadow: shadow.and_then(|shadow| shadow.for_view(view))
+        }
     }
 
-    // Draw all objects
-    fn draw_objects(&mut self, camera: &Camera, frame: &mut Frame, program: &glium::Program, draw_parameters: &DrawParameters<'_>, display: &Display<WindowSurface>) {
-        for obj in &self.objects {
-            obj.draw(&self, camera, frame, program, draw_parameters, display);
+    // Draw all objects, the shadow map has to be rendered already
+    fn draw_objects<S: Surface>(&mut self, frame: &mut S, renderer: &mut Renderer, rect: Option<Rect>, batches: &[Batch], shadow: Option<ShadowData>) {
+        let uniforms = self.get_frame_uniforms(frame.get_dimensions(), shadow, rect);
+        renderer.use_lights(&uniforms);
+
+        // Shadows come from every caster, the camera only draws what it sees
+        for batch in renderer.cull_batches(batches, &self.meshes, &uniforms.frustum) {
+            renderer.draw_batch(frame, &batch, &self.meshes, &uniforms);
         }
     }
 
-    // Adding Objects 
-    pub fn add_object(&mut self, object: &'static mut dyn Object) {
-        self.objects.push(object)
+    pub fn get_meshes(&self) -> &MeshLibrary {
+        &self.meshes
+    }
+
+    pub fn get_meshes_mut(&mut self) -> &mut MeshLibrary {
+        &mut self.meshes
+    }
+
+    // Materials use the image by giving `name` as a texture path
+    pub fn insert_image(&mut self, name: &str, image: RgbaImage) {
+        self.images.insert(name.to_string(), image);
+    }
+
+    pub fn get_image(&self, name: &str) -> Option<&RgbaImage> {
+        self.images.get(name)
+    }
+
+    // Also the clear color
+    pub fn set_ambient_color(&mut self, color: (f32, f32, f32, f32)) {
+        self.ambient_color = color;
     }
 
-    pub fn get_objects(&mut self) -> &Vec<&'static mut dyn Object> {
-        &self.objects
+    pub fn get_ambient_color(&self) -> (f32, f32, f32, f32) {
+        self.ambient_color
     }
 
-    // Clear Screen
-    fn clear(&self, frame: &mut Frame) {
+    // Clear Screen, or only the rectangle
+    fn clear<S: Surface>(&self, frame: &mut S, rect: Option<Rect>) {
         let color = self.ambient_color;
-        frame.clear_color_and_depth((color.0, color.1, color.2, color.3), 1.0)
+        frame.clear(rect.as_ref(), Some((color.0, color.1, color.2, color.3)), false, Some(self.camera.get_clear_depth()), None)
     }
 }
 
-// Objects
-pub trait Object {
-    fn new(name: &'static str) -> &'static mut Self where Self: Sized;
+// Lets `dyn Object` be downcast to its concrete type
+pub trait AsAny {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: Any> AsAny for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
 
-    fn draw(&self, parent_world: &World, camera: &Camera, frame: &mut Frame, program: &glium::Program, draw_parameters: &DrawParameters<'_>, display: &Display<WindowSurface>);
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
 }
 
-const TEST_VS: &str = r#"
-    #version 150
+// Objects
+#[typetag::serde(tag = "type")]
+pub trait Object: AsAny {
+    fn new(name: &str) -> Self where Self: Sized;
 
-    in vec3 position;
-    in vec3 normal;
+    fn get_name(&self) -> &str;
 
-    out vec3 v_normal;
+    fn transform(&self) -> &Transform;
+    fn transform_mut(&mut self) -> &mut Transform;
 
-    uniform mat4 perspective;
-    uniform mat4 view;
-    uniform mat4 model;
-    uniform vec3 global_position;
+    // Objects with the same key share one mesh
+    fn mesh_key(&self) -> Option<String> {
+        None
+    }
 
-    void main() {
-        mat4 modelview = view * model;
-        
-        vec3 render_position = position + global_position;
-        v_normal = transpose(inverse(mat3(modelview))) * normal;
-        gl_Position = perspective * modelview * vec4(render_position, 1.0);
+    // Called only when the key is not in the world's mesh library yet
+    fn build_mesh(&self) -> Option<Mesh> {
+        None
     }
-"#;
 
-const TEST_FS: &str = r#"
-    #version 150
+    // Meshes to add to the library as (key, mesh) pairs, the object's own among them.
+    // Files with several parts return all of them so the file is read only once.
+    fn build_meshes(&self) -> Vec<(String, Mesh)> {
+        self.mesh_key().zip(self.build_mesh()).into_iter().collect()
+    }
 
-    in vec3 v_normal;
-    out vec4 color;
-    uniform vec3 light;
+    // Surface the mesh is drawn with, the default material when None
+    fn material(&self) -> Option<&Material> {
+        None
+    }
 
-    void main() {
-        float brightness = dot(normalize(v_normal), normalize(light));
-        vec3 dark_color = vec3(0.6, 0.0, 0.0);
-        vec3 regular_color = vec3(1.0, 0.0, 0.0);
-        color = vec4(mix(dark_color, regular_color, brightness), 1.0);
+    fn material_mut(&mut self) -> Option<&mut Material> {
+        None
     }
-"#;
+}
 
-#[derive(Default)]
+#[derive(Default, Serialize, Deserialize)]
 pub struct Cuboid {
-    name: &'static str,
-    program: Option<glium::Program>,
+    name: String,
 
-    pub position: Vec3,
-    pub rotation: Vec3,
-    pub size: Vec3
+    pub transform: Transform,
+    pub size: Vec3,
+    #[serde(default)]
+    pub material: Material
 }
 
+#[typetag::serde]
 impl Object for Cuboid {
-    fn new(name: &'static str) -> &'static mut Self where Self: Sized {
-        Box::leak(Box::new(Self {name, ..Default::default()}))
+    fn new(name: &str) -> Self where Self: Sized {
+        let name = name.to_string();
+        let size = Vec3::ONE;
+        Self {name, size, ..Default::default()}
     }
 
-    fn draw(&self, parent_world: &World, camera: &Camera, frame: &mut Frame, program: &glium::Program, draw_parameters: &DrawParameters<'_>, display: &Display<WindowSurface>) {
-        //print!("Drawing {} ", self.name)
-        let perspective = camera.get_perspective(frame);
-        let view = camera.get_view();
+    fn get_name(&self) -> &str {
+        &self.name
+    }
 
-        let model = [
-            [0.01, 0.0, 0.0, 0.0],
-            [0.0, 0.01, 0.0, 0.0],
-            [0.0, 0.0, 0.01, 0.0],
-            [0.0, 0.0, 0.0, 1.0f32]
-        ];
+    fn transform(&self) -> &Transform {
+        &self.transform
+    }
 
-        if self.program.is_some() {
-            //frame.draw(_, _, &self.program.as_ref().unwrap(), uniforms, draw_parameters)
-        };
+    fn transform_mut(&mut self) -> &mut Transform {
+        &mut self.transform
+    }
+
+    // Size is baked into the mesh, cuboids of equal size share it
+    fn mesh_key(&self) -> Option<String> {
+        Some(format!("cuboid:{}x{}x{}", self.size.x, self.size.y, self.size.z))
+    }
+
+    fn build_mesh(&self) -> Option<Mesh> {
+        Some(Mesh::cuboid(self.size))
+    }
+
+    fn material(&self) -> Option<&Material> {
+        Some(&self.material)
+    }
+
+    fn material_mut(&mut self) -> Option<&mut Material> {
+        Some(&mut self.material)
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Serialize, Deserialize)]
 pub struct Teapot {
-    name: &'static str,
+    name: String,
 
-    pub position: Vec3,
-    pub rotation: Vec3,
-    pub scale: Vec3
+    pub transform: Transform,
+    #[serde(default)]
+    pub material: Material
 }
 
+#[typetag::serde]
 impl Object for Teapot {
-    fn new(name: &'static str) -> &'static mut Self where Self: Sized {
-        let scale = Vec3::new(1.0, 1.0, 1.0);
-        Box::leak(Box::new(Self {name, scale, ..Default::default()}))
+    fn new(name: &str) -> Self where Self: Sized {
+        let name = name.to_string();
+        Self {name, ..Default::default()}
     }
 
-    fn draw(&self, parent_world: &World, camera: &Camera, frame: &mut Frame, program: &glium::Program, draw_parameters: &DrawParameters<'_>, display: &Display<WindowSurface>) {
-        //println!("self pos {:?}", self.position.get_tuple());
-        let perspective = camera.get_perspective(frame);
-        let view = camera.get_view();
+    fn get_name(&self) -> &str {
+        &self.name
+    }
 
-        let positions = glium::VertexBuffer::new(display, &teapot::VERTICES).unwrap();
-        let normals = glium::VertexBuffer::new(display, &teapot::NORMALS).unwrap();
-        let indices = glium::IndexBuffer::new(display, glium::index::PrimitiveType::TrianglesList,
-                                            &teapot::INDICES).unwrap();
+    fn transform(&self) -> &Transform {
+        &self.transform
+    }
 
-        let model = [
-            [0.01, 0.0, 0.0, 0.0],
-            [0.0, 0.01, 0.0, 0.0],
-            [0.0, 0.0, 0.01, 0.0],
-            [0.0, 0.0, 2.0, 1.0f32]
-        ];
+    fn transform_mut(&mut self) -> &mut Transform {
+        &mut self.transform
+    }
 
-        //println!("Drawing {} ", self.name);
-        frame.draw((&positions, &normals), &indices, program,
-        &uniform! { model: model, global_position: self.position.get_matrix(), view: view, perspective: perspective, light: parent_world.global_light.get_matrix() },
-        draw_parameters).unwrap();
+    fn mesh_key(&self) -> Option<String> {
+        Some("teapot".to_string())
+    }
+
+    fn build_mesh(&self) -> Option<Mesh> {
+        Some(Mesh::teapot())
+    }
+
+    fn material(&self) -> Option<&Material> {
+        Some(&self.material)
+    }
+
+    fn material_mut(&mut self) -> Option<&mut Material> {
+        Some(&mut self.material)
+    }
+}
+
+// Mesh loaded from a model file
+#[derive(Default, Serialize, Deserialize)]
+pub struct Model {
+    name: String,
+
+    pub transform: Transform,
+    pub path: String,
+    // Single group inside the file, the whole file when None
+    pub part: Option<String>,
+    #[serde(default)]
+    pub material: Material
+}
+
+impl Model {
+    pub fn from_file(name: &str, path: &str, part: Option<&str>) -> Self {
+        let mut model = Self::new(name);
+        model.path = path.to_string();
+        model.part = part.map(|part| part.to_string());
+        model
+    }
+
+    pub fn get_mesh_key(path: &str, part: Option<&str>) -> String {
+        format!("{}#{}", path, part.unwrap_or(""))
+    }
+}
+
+#[typetag::serde]
+impl Object for Model {
+    fn new(name: &str) -> Self where Self: Sized {
+        let name = name.to_string();
+        Self {name, ..Default::default()}
+    }
+
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn transform(&self) -> &Transform {
+        &self.transform
+    }
+
+    fn transform_mut(&mut self) -> &mut Transform {
+        &mut self.transform
+    }
+
+    fn mesh_key(&self) -> Option<String> {
+        match self.path.is_empty() {
+            true => None,
+            false => Some(Model::get_mesh_key(&self.path, self.part.as_deref()))
+        }
+    }
+
+    // Every group or primitive of the file, plus the merged mesh when the whole file is shown
+    fn build_meshes(&self) -> Vec<(String, Mesh)> {
+        let extension = std::path::Path::new(&self.path).extension().and_then(|ext| ext.to_str()).unwrap_or("");
+
+        match extension.to_lowercase().as_str() {
+            "gltf" | "glb" => {
+                let primitives = match gltf_import::load_gltf_meshes(&self.path) {
+                    Ok(primitives) => primitives,
+                    Err(err) => {
+                        println!("Can't load model {}: {}", self.path, err);
+                        return Vec::new();
+                    }
+                };
+
+                let merged = self.part.is_none().then(|| {
+                    let mut merged = Mesh::default();
+                    for (_, primitive) in &primitives {
+                        merged.append(primitive);
+                    }
+                    (Model::get_mesh_key(&self.path, None), merged)
+                });
+                primitives.into_iter()
+                    .map(|(part, mesh)| (Model::get_mesh_key(&self.path, Some(&part)), mesh))
+                    .chain(merged)
+                    .collect()
+            },
+            _ => {
+                let model = match obj::load_obj(&self.path) {
+                    Ok(model) => model,
+                    Err(err) => {
+                        println!("Can't load model {}: {}", self.path, err);
+                        return Vec::new();
+                    }
+                };
+
+                let merged = self.part.is_none().then(|| (Model::get_mesh_key(&self.path, None), model.merged()));
+                model.groups.into_iter()
+                    .map(|group| (Model::get_mesh_key(&self.path, Some(&group.name)), group.mesh))
+                    .chain(merged)
+                    .collect()
+            }
+        }
+    }
+
+    fn material(&self) -> Option<&Material> {
+        Some(&self.material)
+    }
+
+    fn material_mut(&mut self) -> Option<&mut Material> {
+        Some(&mut self.material)
     }
 }
 
@@ -356,12 +690,66 @@ pub struct Settings {
     window_size: PhysicalSize<u32>,
     min_window_size: PhysicalSize<u32>,
 
-    max_fps: u32
+    max_fps: u32,
+    instancing: bool,
+    culling: bool,
+
+    screenshot_key: VirtualKeyCode,
+    record_key: VirtualKeyCode,
+    // Grabs the cursor for mouse look and releases it again
+    grab_key: VirtualKeyCode,
+    // Where screenshots and recordings are saved
+    capture_dir: PathBuf,
+    record_fps: u32,
+    record_on_start: bool,
+    shader_dir: PathBuf
 }
 
 impl Settings {
     pub fn new(title: &'static str, window_size: PhysicalSize<u32>, min_window_size: PhysicalSize<u32>, max_fps: u32) -> Self {
-        Self {title, window_size, min_window_size, max_fps}
+        Self {title, window_size, min_window_size, max_fps, ..Default::default()}
+    }
+
+    // Draw objects sharing a mesh with one call, can also be toggled with I at runtime
+    pub fn set_instancing(&mut self, instancing: bool) {
+        self.instancing = instancing
+    }
+
+    // Skip objects outside the camera's view, can also be toggled with F5 at runtime
+    pub fn set_culling(&mut self, culling: bool) {
+        self.culling = culling
+    }
+
+    pub fn set_screenshot_key(&mut self, key: VirtualKeyCode) {
+        self.screenshot_key = key
+    }
+
+    pub fn set_grab_key(&mut self, key: VirtualKeyCode) {
+        self.grab_key = key
+    }
+
+    // Starts and stops recording
+    pub fn set_record_key(&mut self, key: VirtualKeyCode) {
+        self.record_key = key
+    }
+
+    pub fn set_capture_dir(&mut self, dir: PathBuf) {
+        self.capture_dir = dir
+    }
+
+    // Frame rate of recordings, the world is stepped by 1 / fps per recorded frame
+    pub fn set_record_fps(&mut self, fps: u32) {
+        self.record_fps = fps
+    }
+
+    // Record from the first frame without pressing the record key
+    pub fn set_record_on_start(&mut self, record: bool) {
+        self.record_on_start = record
+    }
+
+    // Where the shaders are read and reloaded from, defaults to $DENGINE_SHADER_DIR or ./shaders
+    pub fn set_shader_dir(&mut self, dir: PathBuf) {
+        self.shader_dir = dir
     }
 }
 
@@ -370,24 +758,35 @@ impl Default for Settings {
         let window_size = PhysicalSize::new(700, 500);
         let min_window_size = PhysicalSize::new(350, 250);
 
-        Self {title: "DEngine", window_size, min_window_size, max_fps: 1200 }
+        Self {
+            title: "DEngine", window_size, min_window_size, max_fps: 1200, instancing: true, culling: true,
+            screenshot_key: VirtualKeyCode::F12, record_key: VirtualKeyCode::F10, grab_key: VirtualKeyCode::Tab,
+            capture_dir: PathBuf::from("screenshots"), record_fps: 30, record_on_start: false,
+            shader_dir: default_shader_dir()
+        }
     }
 }
 
+// Gameplay callback, receives the frame time in seconds
+pub type UpdateFn = Box<dyn FnMut(&mut World, f32)>;
+
 pub struct Engine {
-    pub camera: Camera,
-    world: Option<&'static mut World>,
-    pub settings: Settings
+    world: Option<World>,
+    update: Option<UpdateFn>,
+    pub settings: Settings,
+    // Moves the world's camera
+    pub controller: CameraController
 }
 
 impl Engine {
-    pub fn new() -> &'static mut Self {
-        let camera = Camera::new();
+    pub fn new() -> Self {
         let world = None;
+        let update = None;
 
         let settings = Settings::default();
+        let controller = CameraController::new();
 
-        Box::leak(Box::new(Self {camera, world, settings}))
+        Self {world, update, settings, controller}
     }
 
     fn get_delta_time(&self, start_time: Instant) -> u32 {
@@ -399,14 +798,7 @@ impl Engine {
         }
     }
 
-    fn get_fps(&self, delta: u32) -> u32 {
-        match delta > 0 {
-            true => 1_000_000_000 / delta,
-            false => 0
-        }
-    }
-
-    pub fn run(&'static mut self) {
+    pub fn run(mut self) {
         let event_loop = EventLoop::new();
 
         let (window, display) = SimpleWindowBuilder::new().build(&event_loop);
@@ -416,25 +808,38 @@ impl Engine {
         window.set_inner_size(self.settings.window_size);
         window.set_min_inner_size(Some(self.settings.min_window_size));
 
-        let f11_key = Key::new(0.3, VirtualKeyCode::F11);
-        let w_key = Key::new(0.01, VirtualKeyCode::W);
-
-        let params = glium::DrawParameters {
-            depth: glium::Depth {
-                test: glium::draw_parameters::DepthTest::IfLess,
-                write: true,
-                .. Default::default()
-            },
-            backface_culling: glium::draw_parameters::BackfaceCullingMode::CullClockwise,
-            .. Default::default()
+        let mut f11_key = Key::new(0.3, VirtualKeyCode::F11);
+        let mut i_key = Key::new(0.3, VirtualKeyCode::I);
+        let mut f3_key = Key::new(0.3, VirtualKeyCode::F3);
+        let mut f4_key = Key::new(0.3, VirtualKeyCode::F4);
+        let mut f5_key = Key::new(0.3, VirtualKeyCode::F5);
+        let mut screenshot_key = Key::new(0.3, self.settings.screenshot_key);
+        let mut record_key = Key::new(0.3, self.settings.record_key);
+        let mut grab_key = Key::new(0.3, self.settings.grab_key);
+        let mut c_key = Key::new(0.3, VirtualKeyCode::C);
+        let mut f_key = Key::new(0.3, VirtualKeyCode::F);
+        let mut input = InputState::new();
+
+        // Taken after the frame is finished
+        let mut take_screenshot = false;
+        let mut recorder = match self.settings.record_on_start {
+            true => Some(Recorder::new(&self.settings.capture_dir, self.settings.record_fps)),
+            false => None
         };
 
-        let program = glium::Program::from_source(&display, TEST_VS, TEST_FS, None).unwrap();
+        let mut renderer = Renderer::with_shader_dir(&display, self.settings.instancing, &self.settings.shader_dir);
+        renderer.culling = self.settings.culling;
+
+        let mut last_frame = Instant::now();
+        // Printed once the window closes
+        let mut frame_times = FrameTimes::new();
+        let mut warned_lights = false;
 
         event_loop.run(move |event, _, control_flow| {
             control_flow.set_wait();
             control_flow.set_poll();
             let start_time = Instant::now();
+            input.handle_event(&event);
 
             // Смена полноэкранного режима 
             if f11_key.is_pressed(&event) {
@@ -446,10 +851,88 @@ impl Engine {
                 }
             }
 
-            if w_key.is_pressed(&event) {
-                //println!("{:?}", self.camera.direction.get_tuple());
-                //println!("{:?}", self.camera.direction.sin().get_tuple());
-                //self.camera.position += self.camera.direction.sin().mul_f32(self.camera.speed);
+            // Instancing on/off
+            if i_key.is_pressed(&event) {
+                renderer.instancing = !renderer.instancing;
+                println!("Instancing: {}", renderer.instancing);
+            }
+
+            // Frustum culling on/off
+            if f5_key.is_pressed(&event) {
+                renderer.culling = !renderer.culling;
+                println!("Culling: {}", renderer.culling);
+            }
+
+            // Debug gizmos on/off
+            if f3_key.is_pressed(&event) {
+                if let Some(world) = &mut self.world {
+                    let debug = world.get_debug_mut();
+                    debug.enabled = !debug.enabled;
+                    println!("Debug draw: {}", debug.enabled);
+                }
+            }
+
+            // Shadows on/off
+            if f4_key.is_pressed(&event) {
+                if let Some(world) = &mut self.world {
+                    let shadows = world.get_shadows_mut();
+                    shadows.enabled = !shadows.enabled;
+                    println!("Shadows: {}", shadows.enabled);
+                }
+            }
+
+            if screenshot_key.is_pressed(&event) {
+                take_screenshot = true;
+            }
+
+            // Recording on/off
+            if record_key.is_pressed(&event) {
+                recorder = match recorder.take() {
+                    Some(recorder) => {
+                        println!("Recorded {} frames to {}", recorder.get_frame_count(), recorder.get_dir().display());
+                        None
+                    },
+                    None => {
+                        let recorder = Recorder::new(&self.settings.capture_dir, self.settings.record_fps);
+                        println!("Recording to {}", recorder.get_dir().display());
+                        Some(recorder)
+                    }
+                };
+            }
+
+            // Mouse look on/off, a window losing focus lets go of the cursor
+            if grab_key.is_pressed(&event) && self.controller.get_mode() == CameraMode::Fly {
+                self.controller.fly.mouse_look = controller::grab_cursor(&window, !self.controller.fly.mouse_look);
+            }
+            if let Event::WindowEvent {event: WindowEvent::Focused(false), ..} = event {
+                self.controller.fly.mouse_look = controller::grab_cursor(&window, false);
+            }
+
+            // Camera mode: fly, orbit or fixed
+            if c_key.is_pressed(&event) {
+                if let Some(world) = &self.world {
+                    let mode = self.controller.next_mode(&world.camera);
+                    if mode != CameraMode::Fly {
+                        self.controller.fly.mouse_look = controller::grab_cursor(&window, false);
+                    }
+                    println!("Camera: {:?}", mode);
+                }
+            }
+
+            // Orbit the selected object, or the whole scene without a selection
+            if f_key.is_pressed(&event) {
+                if let Some(world) = &mut self.world {
+                    world.update_transforms();
+                    let bounds = match world.get_selected() {
+                        Some(id) => world.get_bounds(id),
+                        None => world.get_scene_bounds()
+                    };
+                    if let Some(bounds) = bounds {
+                        let size = window.inner_size();
+                        self.controller.frame(&mut world.camera, bounds, size.width as f32 / size.height.max(1) as f32);
+                        self.controller.fly.mouse_look = controller::grab_cursor(&window, false);
+                    }
+                }
             }
 
             match event {
@@ -458,33 +941,72 @@ impl Engine {
                     ..
                 } => {
                     println!("Закрытие программы...");
+                    let stats = renderer.get_stats();
+                    println!("Last frame: {} objects, {} culled, {} draw calls, {} lights, {} shadow casters", stats.objects, stats.culled, stats.draw_calls, stats.lights, stats.shadow_casters);
+                    for (instanced, label) in [(true, "instanced"), (false, "one call per object")] {
+                        if let Some(summary) = frame_times.get_summary(instanced) {
+                            println!("World drawing time, {}: {}", label, summary);
+                        }
+                    }
                     control_flow.set_exit()
                 },
                 Event::RedrawRequested(_) => {
+                    // Recordings step the world at a fixed rate
+                    let frame_time = match &recorder {
+                        Some(recorder) => recorder.get_timestep(),
+                        None => last_frame.elapsed().as_secs_f32()
+                    };
+                    last_frame = Instant::now();
+
+                    // Gameplay update
+                    if let (Some(update), Some(world)) = (&mut self.update, &mut self.world) {
+                        update(world, frame_time);
+                    }
+
+                    if let Some(world) = &mut self.world {
+                        self.controller.update(&mut world.camera, &input, frame_time);
+                    }
+                    input.end_frame();
+
+                    renderer.reload_shaders();
+
                     // Создание кадра
                     let mut frame = display.draw();
 
-                    self.camera.direction.x += radians(10.0);
-
                     // Clear screen
-                    if self.world.is_some() {
-                        self.world.as_mut().unwrap().clear(&mut frame);
-
-                        // Draw Axis
-                        self.world.as_mut().unwrap().draw_axis(&mut frame, &self.camera, &display);
-                    } else {
+                    if self.world.is_none() {
                         frame.clear_color(0.0, 0.0, 0.0, 1.0);
                     }
 
                     // GUI?
 
-                    // Draw world objects
-                    if self.world.is_some() {
+                    // Draw every view of the world, each clears its own part of the screen
+                    if let Some(world) = &mut self.world {
                         let start_drawing = Instant:: now();
-                        self.world.as_mut().unwrap().draw_objects(&self.camera, &mut frame, &program, &params, &display);
+                        renderer.begin_frame();
+                        world.draw_window(&mut frame, &mut renderer);
+
+                        frame_times.add(start_drawing.elapsed().as_secs_f32() * 1000.0, renderer.instancing);
+                        let stats = renderer.get_stats();
+                        if stats.dropped_lights > 0 && !warned_lights {
+                            warned_lights = true;
+                            println!("{} lights over the limit were skipped", stats.dropped_lights);
+                        }
+                    }
+
+                    // Captures read the back buffer, so they go before the swap
+                    if take_screenshot {
+                        take_screenshot = false;
+                        match capture::read_frame(&display, &frame).and_then(|image| capture::save_screenshot(&image, &self.settings.capture_dir)) {
+                            Ok(path) => println!("Saved screenshot {}", path.display()),
+                            Err(err) => println!("Can't save screenshot: {}", err)
+                        }
+                    }
 
-                        let draw_time = Instant::now().duration_since(start_drawing).as_nanos() as u32;
-                        println!("World drawing time: {}", self.get_fps(draw_time));
+                    if let Some(recorder) = &mut recorder {
+                        if let Err(err) = capture::read_frame(&display, &frame).and_then(|image| recorder.save(&image)) {
+                            println!("Can't record frame: {}", err);
+                        }
                     }
 
                     // Завершение отрисовки кадра.
@@ -508,11 +1030,114 @@ impl Engine {
         });
     }
 
-    pub fn set_world(&mut self, world: Option<&'static mut World>) {
+    pub fn set_world(&mut self, world: Option<World>) {
         self.world = world
     }
 
-    pub fn get_world(&self) -> &Option<&'static mut World> {
-        &self.world
+    pub fn get_world(&self) -> Option<&World> {
+        self.world.as_ref()
+    }
+
+    pub fn get_world_mut(&mut self) -> Option<&mut World> {
+        self.world.as_mut()
+    }
+
+    // Called once per frame before drawing
+    pub fn set_update<F: FnMut(&mut World, f32) + 'static>(&mut self, update: F) {
+        self.update = Some(Box::new(update))
+    }
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Depth of a point at `distance` in front of the camera, after the perspective divide
+    fn depth(projection: Mat4, distance: f32) -> f32 {
+        projection.transform_point(Vec3::new(0.0, 0.0, distance)).z
+    }
+
+    fn assert_near(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() <= 1e-5, "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn orthographic_maps_the_box() {
+        let mut camera = Camera::new();
+        camera.set_projection(Projection::Orthographic {size: 4.0});
+        camera.set_clip_planes(1.0, 11.0);
+
+        let projection = camera.projection_matrix(2.0);
+        let corner = projection.transform_point(Vec3::new(4.0, 2.0, 1.0));
+        assert_near(corner.x, 1.0);
+        assert_near(corner.y, 1.0);
+        assert_near(corner.z, -1.0);
+        assert_near(depth(projection, 11.0), 1.0);
+        // No perspective: depth is linear in distance
+        assert_near(depth(projection, 6.0), 0.0);
+    }
+
+    #[test]
+    fn reverse_z_puts_near_at_one_and_far_at_zero() {
+        let mut camera = Camera::new();
+        camera.set_clip_planes(0.5, 10.0);
+        camera.set_reverse_z(true);
+
+        let projection = camera.projection_matrix(1.0);
+        assert_near(depth(projection, 0.5), 1.0);
+        assert_near(depth(projection, 10.0), 0.0);
+        assert!(depth(projection, 2.0) > depth(projection, 3.0));
+        assert_eq!(camera.get_clear_depth(), 0.0);
+
+        camera.set_projection(Projection::Orthographic {size: 2.0});
+        let projection = camera.projection_matrix(1.0);
+        assert_near(depth(projection, 0.5), 1.0);
+        assert_near(depth(projection, 10.0), 0.0);
+
+        // Culling doesn't care which way depth goes
+        let frustum = camera.get_frustum(1.0);
+        assert!(frustum.contains_point(Vec3::new(0.0, 0.0, 5.0)));
+        assert!(!frustum.contains_point(Vec3::new(0.0, 0.0, 11.0)));
+    }
+
+    #[test]
+    fn infinite_far_never_reaches_the_far_plane() {
+        let mut camera = Camera::new();
+        camera.set_clip_planes(0.5, 10.0);
+        camera.set_infinite_far(true);
+
+        let projection = camera.projection_matrix(1.0);
+        assert_near(depth(projection, 0.5), -1.0);
+        assert!(depth(projection, 1e6) < 1.0 && depth(projection, 1e6) > 0.999);
+        assert!(camera.get_frustum(1.0).contains_point(Vec3::new(0.0, 0.0, 1e6)));
+
+        // Reverse-Z keeps far away depths apart, they are all close to 0 instead of 1
+        camera.set_reverse_z(true);
+        let projection = camera.projection_matrix(1.0);
+        assert_near(depth(projection, 0.5), 1.0);
+        assert_near(depth(projection, 5.0), 0.1);
+        assert!(depth(projection, 1e6) > 0.0 && depth(projection, 1e6) > depth(projection, 2e6));
+    }
+
+    #[test]
+    fn frame_uniforms_cull_past_zfar_with_reverse_z() {
+        let mut world = World::new("Culling");
+        world.camera.set_clip_planes(0.5, 10.0);
+        world.camera.set_reverse_z(true);
+
+        let frustum = world.get_frame_uniforms((800, 600), None, None).frustum;
+        assert!(frustum.intersects_sphere(&math::BoundingSphere::new(Vec3::new(0.0, 0.0, 5.0), 1.0)));
+        assert!(!frustum.intersects_sphere(&math::BoundingSphere::new(Vec3::new(0.0, 0.0, 20.0), 1.0)));
+        // Viewports bring their own aspect ratio
+        let rect = Rect {left: 0, bottom: 0, width: 100, height: 400};
+        let frustum = world.get_frame_uniforms((800, 600), None, Some(rect)).frustum;
+        assert!(!frustum.intersects_sphere(&math::BoundingSphere::new(Vec3::new(4.0, 0.0, 5.0), 1.0)));
     }
 }
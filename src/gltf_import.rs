@@ -0,0 +1,378 @@
+// glTF 2.0 (.gltf / .glb) import
+use std::fmt;
+use std::path::Path;
+
+use image::RgbaImage;
+use serde::{Serialize, Deserialize};
+
+use super::math::{Quat, Vec3};
+use super::mesh::Mesh;
+use super::scene::NodeId;
+use super::transform::Transform;
+use super::{Material, Model, Object, World};
+
+#[derive(Debug)]
+pub struct GltfError(::gltf::Error);
+
+impl fmt::Display for GltfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "gltf error: {}", self.0)
+    }
+}
+
+impl std::error::Error for GltfError {}
+
+impl From<::gltf::Error> for GltfError {
+    fn from(err: ::gltf::Error) -> Self {
+        GltfError(err)
+    }
+}
+
+// Image used by a material
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TextureRef {
+    // Index into the file's images
+    pub image: usize,
+    // Path relative to the file, None when the image is embedded
+    pub uri: Option<String>
+}
+
+// Metallic-roughness material as stored in the file
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PbrMaterial {
+    pub name: String,
+    pub base_color: [f32; 4],
+    pub metallic: f32,
+    pub roughness: f32,
+
+    pub base_color_texture: Option<TextureRef>,
+    pub metallic_roughness_texture: Option<TextureRef>,
+    pub normal_texture: Option<TextureRef>
+}
+
+impl Default for PbrMaterial {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            base_color: [1.0; 4],
+            metallic: 1.0,
+            roughness: 1.0,
+            base_color_texture: None,
+            metallic_roughness_texture: None,
+            normal_texture: None
+        }
+    }
+}
+
+fn texture_ref(texture: ::gltf::Texture<'_>) -> TextureRef {
+    let image = texture.source();
+    let uri = match image.source() {
+        ::gltf::image::Source::Uri {uri, ..} if !uri.starts_with("data:") => Some(uri.to_string()),
+        _ => None
+    };
+
+    TextureRef {image: image.index(), uri}
+}
+
+fn read_material(material: ::gltf::Material<'_>) -> PbrMaterial {
+    let pbr = material.pbr_metallic_roughness();
+
+    PbrMaterial {
+        name: material.name().unwrap_or("").to_string(),
+        base_color: pbr.base_color_factor(),
+        metallic: pbr.metallic_factor(),
+        roughness: pbr.roughness_factor(),
+        base_color_texture: pbr.base_color_texture().map(|info| texture_ref(info.texture())),
+        metallic_roughness_texture: pbr.metallic_roughness_texture().map(|info| texture_ref(info.texture())),
+        normal_texture: material.normal_texture().map(|info| texture_ref(info.texture()))
+    }
+}
+
+// Part name used as the mesh key of one primitive
+pub fn primitive_part(mesh: usize, primitive: usize) -> String {
+    format!("mesh{}/{}", mesh, primitive)
+}
+
+// Texture path materials use for an image stored inside the file
+pub fn image_name(path: &str, image: usize) -> String {
+    format!("{}#image{}", path, image)
+}
+
+// Decoded pixels as 8-bit RGBA; gray images are spread over the color channels
+fn to_rgba(data: &::gltf::image::Data) -> Option<RgbaImage> {
+    use ::gltf::image::Format;
+
+    // Channels per pixel and bytes per channel
+    let (channels, size) = match data.format {
+        Format::R8 => (1, 1),
+        Format::R8G8 => (2, 1),
+        Format::R8G8B8 => (3, 1),
+        Format::R8G8B8A8 => (4, 1),
+        Format::R16 => (1, 2),
+        Format::R16G16 => (2, 2),
+        Format::R16G16B16 => (3, 2),
+        Format::R16G16B16A16 => (4, 2),
+        Format::R32G32B32FLOAT => (3, 4),
+        Format::R32G32B32A32FLOAT => (4, 4)
+    };
+    let channel = |bytes: &[u8]| match bytes {
+        [value] => *value,
+        [a, b] => (u16::from_ne_bytes([*a, *b]) >> 8) as u8,
+        _ => (f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]).clamp(0.0, 1.0) * 255.0).round() as u8
+    };
+
+    let pixels = data.pixels.chunks_exact(channels * size).flat_map(|pixel| {
+        let c: Vec<u8> = pixel.chunks_exact(size).map(channel).collect();
+        match channels {
+            1 => [c[0], c[0], c[0], 255],
+            2 => [c[0], c[0], c[0], c[1]],
+            3 => [c[0], c[1], c[2], 255],
+            _ => [c[0], c[1], c[2], c[3]]
+        }
+    }).collect();
+    RgbaImage::from_raw(data.width, data.height, pixels)
+}
+
+// Images embedded in the file (data uris and GLB buffer views) by their `image_name`.
+// Files next to it are left out, materials load them by path.
+fn embedded_images(document: &::gltf::Document, images: &[::gltf::image::Data], path: &str) -> Vec<(String, RgbaImage)> {
+    document.images().zip(images).filter_map(|(image, data)| {
+        let embedded = match image.source() {
+            ::gltf::image::Source::Uri {uri, ..} => uri.starts_with("data:"),
+            ::gltf::image::Source::View {..} => true
+        };
+        match embedded {
+            true => to_rgba(data).map(|rgba| (image_name(path, image.index()), rgba)),
+            false => None
+        }
+    }).collect()
+}
+
+// glTF is right-handed, the engine left-handed: Z is mirrored. The mirror also turns
+// counter-clockwise front faces clockwise, so the index order stays as it is.
+fn mirror_z([x, y, z]: [f32; 3]) -> [f32; 3] {
+    [x, y, -z]
+}
+
+fn read_primitive(primitive: &::gltf::Primitive<'_>, buffers: &[::gltf::buffer::Data]) -> Option<Mesh> {
+    use ::gltf::mesh::Mode;
+
+    let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(|data| &data.0[..]));
+
+    let positions: Vec<[f32; 3]> = reader.read_positions()?.map(mirror_z).collect();
+    // glTF puts v = 0 at the top of the image, the engine at the bottom like OBJ
+    let uvs: Vec<[f32; 2]> = reader.read_tex_coords(0).map(|uvs| uvs.into_f32().map(|[u, v]| [u, 1.0 - v]).collect()).unwrap_or_default();
+    let normals: Option<Vec<[f32; 3]>> = reader.read_normals().map(|normals| normals.map(mirror_z).collect());
+
+    let raw: Vec<u32> = match reader.read_indices() {
+        Some(indices) => indices.into_u32().collect(),
+        None => (0..positions.len() as u32).collect()
+    };
+
+    // Strips and fans become plain triangle lists, points and lines are skipped
+    let indices = match primitive.mode() {
+        Mode::Triangles => raw,
+        Mode::TriangleStrip => (2..raw.len()).flat_map(|i| match i % 2 {
+            0 => [raw[i - 2], raw[i - 1], raw[i]],
+            _ => [raw[i - 1], raw[i - 2], raw[i]]
+        }).collect(),
+        Mode::TriangleFan => (2..raw.len()).flat_map(|i| [raw[0], raw[i - 1], raw[i]]).collect(),
+        _ => return None
+    };
+
+    let has_normals = normals.is_some();
+    let mut mesh = Mesh::new(positions, normals.unwrap_or_default(), uvs, indices);
+    if !has_normals {
+        mesh.compute_normals();
+    }
+    Some(mesh)
+}
+
+// Every primitive of the file by its `primitive_part`, in file order
+pub fn load_gltf_meshes<P: AsRef<Path>>(path: P) -> Result<Vec<(String, Mesh)>, GltfError> {
+    let (document, buffers, _) = ::gltf::import(path)?;
+
+    Ok(document.meshes().flat_map(|mesh| {
+        let buffers = &buffers;
+        mesh.primitives().filter_map(move |primitive| {
+            read_primitive(&primitive, buffers).map(|data| (primitive_part(mesh.index(), primitive.index()), data))
+        })
+    }).collect())
+}
+
+// Embedded images of the file by their `image_name`
+pub fn load_gltf_images(path: &str) -> Result<Vec<(String, RgbaImage)>, GltfError> {
+    let (document, _, images) = ::gltf::import(path)?;
+    Ok(embedded_images(&document, &images, path))
+}
+
+// Texture uris are relative to the glTF file, embedded images are named by `image_name`
+fn to_material(pbr: &PbrMaterial, path: &str) -> Material {
+    let base = Path::new(path).parent().unwrap_or(Path::new(""));
+    let resolve = |texture: &Option<TextureRef>| texture.as_ref().map(|texture| match &texture.uri {
+        Some(uri) => base.join(uri).to_string_lossy().into_owned(),
+        None => image_name(path, texture.image)
+    });
+
+    let mut material = Material::from(pbr);
+    material.diffuse_texture = resolve(&pbr.base_color_texture);
+    material.normal_texture = resolve(&pbr.normal_texture);
+    material
+}
+
+// Mirrored like the vertices: the rotation axis flips in X and Y, the angle stays
+fn read_transform(node: &::gltf::Node<'_>) -> Transform {
+    let (translation, rotation, scale) = node.transform().decomposed();
+
+    Transform::new(
+        Vec3::from_matrix(mirror_z(translation)),
+        Quat::new(-rotation[0], -rotation[1], rotation[2], rotation[3]),
+        Vec3::from_matrix(scale)
+    )
+}
+
+impl World {
+    // Adds the default scene (or the first one) under an empty root named after the file.
+    // Nodes keep their hierarchy and local transforms; a mesh with several primitives gets
+    // one child per primitive. Meshes and embedded images go straight into the world so the
+    // file is read once.
+    pub fn load_gltf(&mut self, path: &str) -> Result<NodeId, GltfError> {
+        let (document, buffers, images) = ::gltf::import(path)?;
+        for (name, image) in embedded_images(&document, &images, path) {
+            self.insert_image(&name, image);
+        }
+
+        let name = Path::new(path).file_stem().and_then(|stem| stem.to_str()).unwrap_or(path);
+        let root = self.add_object(Model::new(name));
+
+        let scene = document.default_scene().or_else(|| document.scenes().next());
+        if let Some(scene) = scene {
+            for node in scene.nodes() {
+                self.add_gltf_node(path, &node, &buffers, root);
+            }
+        }
+
+        Ok(root)
+    }
+
+    fn add_gltf_node(&mut self, path: &str, node: &::gltf::Node<'_>, buffers: &[::gltf::buffer::Data], parent: NodeId) {
+        let name = node.name().map(|name| name.to_string()).unwrap_or_else(|| format!("node{}", node.index()));
+
+        let mut model = Model::new(&name);
+        *model.transform_mut() = read_transform(node);
+
+        let mut primitives = Vec::new();
+        if let Some(mesh) = node.mesh() {
+            for primitive in mesh.primitives() {
+                let Some(data) = read_primitive(&primitive, buffers) else {
+                    continue;
+                };
+
+                let part = primitive_part(mesh.index(), primitive.index());
+                self.get_meshes_mut().insert(&Model::get_mesh_key(path, Some(&part)), data);
+                primitives.push((part, to_material(&read_material(primitive.material()), path)));
+            }
+        }
+
+        // A single primitive lives on the node itself
+        if primitives.len() == 1 {
+            let (part, material) = primitives.remove(0);
+            model.path = path.to_string();
+            model.part = Some(part);
+            model.material = material;
+        }
+
+        let Some(id) = self.add_child(parent, model) else {
+            return;
+        };
+
+        for (part, material) in primitives {
+            let mut child = Model::from_file(&format!("{}/{}", name, part), path, Some(&part));
+            child.material = material;
+            self.add_child(id, child);
+        }
+
+        for child in node.children() {
+            self.add_gltf_node(path, &child, buffers, id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> String {
+        format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+
+    #[test]
+    fn primitives_are_mirrored_into_the_engine() {
+        for path in [fixture("embedded.gltf"), fixture("embedded.glb")] {
+            let primitives = load_gltf_meshes(&path).unwrap();
+            let parts: Vec<&str> = primitives.iter().map(|(part, _)| part.as_str()).collect();
+            assert_eq!(parts, ["mesh0/0", "mesh0/1"]);
+
+            // Facing +Z in the file, so -Z here. The strip has no normals, they come from the winding.
+            let (quad, strip) = (&primitives[0].1, &primitives[1].1);
+            assert!(quad.positions.iter().all(|p| p[2] == 1.0));
+            assert_eq!(quad.uvs[0], [0.0, 0.0]);
+            assert_eq!(strip.triangle_count(), 1);
+            for mesh in [quad, strip] {
+                for triangle in mesh.indices.chunks_exact(3) {
+                    let [a, b, c] = [0, 1, 2].map(|i| Vec3::from_matrix(mesh.positions[triangle[i] as usize]));
+                    assert!((c - a).cross(b - a).normalize().approx_eq(-Vec3::Z, 1e-6));
+                }
+                assert!(mesh.normals.iter().all(|n| Vec3::from_matrix(*n).approx_eq(-Vec3::Z, 1e-6)));
+            }
+        }
+    }
+
+    #[test]
+    fn embedded_files_keep_hierarchy_and_images() {
+        for path in [fixture("embedded.gltf"), fixture("embedded.glb")] {
+            let mut world = World::new("Gltf");
+            let root = world.load_gltf(&path).unwrap();
+            assert_eq!(world.get_object(root).unwrap().get_name(), "embedded");
+
+            // Translation and the quarter turn around Y, both mirrored
+            let node = world.get_children(root)[0];
+            let transform = world.get_local_transform(node).unwrap();
+            assert!(transform.translation.approx_eq(Vec3::new(1.0, 0.0, 2.0), 1e-6));
+            assert!(transform.right().approx_eq(Vec3::Z, 1e-6));
+
+            let quad = world.get_children(node)[0];
+            let primitives = world.get_children(quad).to_vec();
+            assert_eq!(primitives.len(), 2);
+            let checker = world.get_as::<Model>(primitives[0]).unwrap();
+            let texture = image_name(&path, 0);
+            assert_eq!(checker.material.diffuse_texture.as_deref(), Some(texture.as_str()));
+            assert_eq!(world.get_as::<Model>(primitives[1]).unwrap().material.diffuse_texture, None);
+
+            let image = world.get_image(&texture).unwrap();
+            assert_eq!(image.dimensions(), (2, 2));
+            assert_eq!(image.get_pixel(0, 0).0, [255, 0, 0, 255]);
+            assert_eq!(image.get_pixel(1, 1).0, [255, 255, 255, 255]);
+            assert_eq!(load_gltf_images(&path).unwrap()[0].1, *image);
+
+            world.update_transforms();
+            assert!(primitives.iter().all(|id| world.get_node(*id).unwrap().get_mesh().is_some()));
+            assert_eq!(world.get_meshes().len(), 2);
+        }
+    }
+
+    #[test]
+    fn models_fill_the_library_from_one_read() {
+        let path = fixture("embedded.glb");
+        let mut world = World::new("Gltf");
+        let quad = world.add_object(Model::from_file("Quad", &path, Some(&primitive_part(0, 0))));
+        let whole = world.add_object(Model::from_file("Whole", &path, None));
+        world.update_transforms();
+
+        // Both primitives and the merged mesh
+        assert_eq!(world.get_meshes().len(), 3);
+        assert_eq!(world.get_meshes().get(world.get_node(quad).unwrap().get_mesh().unwrap()).triangle_count(), 2);
+        assert_eq!(world.get_meshes().get(world.get_node(whole).unwrap().get_mesh().unwrap()).triangle_count(), 3);
+        assert!(matches!(load_gltf_meshes(fixture("missing.glb")), Err(GltfError(_))));
+    }
+}
@@ -0,0 +1,299 @@
+// Rendering without a window, for tests and tools. Uses a surfaceless EGL context, so
+// Mesa's software rasterizer (llvmpipe) is enough and no GPU or X server is needed.
+use std::ffi::CString;
+use std::fmt;
+use std::os::raw::c_void;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use glium::backend::{Backend, Context, Facade};
+use glium::debug::DebugCallbackBehavior;
+use glium::framebuffer::{DepthRenderBuffer, SimpleFrameBuffer};
+use glium::glutin::api::egl;
+use glium::glutin::config::{ConfigSurfaceTypes, ConfigTemplateBuilder};
+use glium::glutin::context::ContextAttributesBuilder;
+use glium::glutin::prelude::*;
+use glium::texture::{DepthFormat, MipmapsOption, RawImage2d, Texture2d, UncompressedFloatFormat};
+use glium::SwapBuffersError;
+use image::RgbaImage;
+
+use super::renderer::Renderer;
+use super::{Camera, World};
+
+#[derive(Debug)]
+pub enum HeadlessError {
+    Egl(glium::glutin::error::Error),
+    // No EGL device could make a context
+    NoDevice,
+    Context(glium::IncompatibleOpenGl),
+    // Creating or reading the offscreen targets failed
+    Target(String),
+    Image(PathBuf, image::ImageError),
+    // Sizes of the two images compared
+    SizeMismatch((u32, u32), (u32, u32))
+}
+
+impl fmt::Display for HeadlessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HeadlessError::Egl(err) => write!(f, "egl error: {}", err),
+            HeadlessError::NoDevice => write!(f, "no egl device can create an opengl context"),
+            HeadlessError::Context(err) => write!(f, "{}", err),
+            HeadlessError::Target(err) => write!(f, "offscreen target error: {}", err),
+            HeadlessError::Image(path, err) => write!(f, "image error in {}: {}", path.display(), err),
+            HeadlessError::SizeMismatch(a, b) => write!(f, "image sizes differ: {}x{} and {}x{}", a.0, a.1, b.0, b.1)
+        }
+    }
+}
+
+impl std::error::Error for HeadlessError {}
+
+impl From<glium::glutin::error::Error> for HeadlessError {
+    fn from(err: glium::glutin::error::Error) -> Self {
+        HeadlessError::Egl(err)
+    }
+}
+
+// Context without any surface, glium only ever draws into our framebuffers with it
+struct EglBackend {
+    display: egl::display::Display,
+    context: egl::context::PossiblyCurrentContext
+}
+
+unsafe impl Backend for EglBackend {
+    fn swap_buffers(&self) -> Result<(), SwapBuffersError> {
+        Ok(())
+    }
+
+    unsafe fn get_proc_address(&self, symbol: &str) -> *const c_void {
+        let symbol = CString::new(symbol).unwrap();
+        self.display.get_proc_address(&symbol) as *const _
+    }
+
+    // There is no default framebuffer
+    fn get_framebuffer_dimensions(&self) -> (u32, u32) {
+        (1, 1)
+    }
+
+    fn resize(&self, _new_size: (u32, u32)) {}
+
+    fn is_current(&self) -> bool {
+        self.context.is_current()
+    }
+
+    unsafe fn make_current(&self) {
+        self.context.make_current_surfaceless().unwrap();
+    }
+}
+
+// Software devices come first so images match across machines
+fn create_backend() -> Result<EglBackend, HeadlessError> {
+    let mut devices: Vec<egl::device::Device> = egl::device::Device::query_devices()?.collect();
+    devices.sort_by_key(|device| !device.extensions().contains("EGL_MESA_device_software"));
+
+    for device in devices {
+        // Safe since no raw display is passed
+        let Ok(display) = (unsafe { egl::display::Display::with_device(&device, None) }) else {
+            continue;
+        };
+
+        let template = ConfigTemplateBuilder::new().with_surface_type(ConfigSurfaceTypes::empty()).build();
+        let Some(config) = (unsafe { display.find_configs(template) }).ok().and_then(|mut configs| configs.next()) else {
+            continue;
+        };
+
+        let attributes = ContextAttributesBuilder::new().build(None);
+        let Ok(context) = (unsafe { display.create_context(&config, &attributes) }) else {
+            continue;
+        };
+        let Ok(context) = context.make_current_surfaceless() else {
+            continue;
+        };
+        return Ok(EglBackend {display, context});
+    }
+    Err(HeadlessError::NoDevice)
+}
+
+// Renders worlds into images instead of a window
+pub struct HeadlessRenderer {
+    context: Rc<Context>,
+    pub renderer: Renderer
+}
+
+impl Facade for HeadlessRenderer {
+    fn get_context(&self) -> &Rc<Context> {
+        &self.context
+    }
+}
+
+impl HeadlessRenderer {
+    pub fn new() -> Result<Self, HeadlessError> {
+        let backend = create_backend()?;
+        // Safe since the backend made its context current
+        let context = unsafe { Context::new(backend, true, DebugCallbackBehavior::Ignore) }.map_err(HeadlessError::Context)?;
+        let renderer = Renderer::new(&context, true);
+        Ok(Self {context, renderer})
+    }
+
+    // One frame of the world as seen from `camera`, top row first like image files
+    pub fn render(&mut self, world: &mut World, camera: &Camera, width: u32, height: u32) -> Result<RgbaImage, HeadlessError> {
+        let target = |err: &dyn fmt::Display| HeadlessError::Target(err.to_string());
+        let color = Texture2d::empty_with_format(&self.context, UncompressedFloatFormat::U8U8U8U8, MipmapsOption::NoMipmap, width, height)
+            .map_err(|err| target(&err))?;
+        let depth = DepthRenderBuffer::new(&self.context, DepthFormat::F32, width, height).map_err(|err| target(&err))?;
+        let mut frame = SimpleFrameBuffer::with_depth_buffer(&self.context, &color, &depth).map_err(|err| target(&err))?;
+
+        let window_camera = std::mem::replace(&mut world.camera, camera.clone());
+        self.renderer.begin_frame();
+        world.draw(&mut frame, &mut self.renderer);
+        world.camera = window_camera;
+
+        let pixels: RawImage2d<u8> = color.read();
+        let image = RgbaImage::from_raw(width, height, pixels.data.into_owned()).ok_or_else(|| target(&"unexpected pixel format"))?;
+        // GL rows start at the bottom
+        Ok(image::imageops::flip_vertical(&image))
+    }
+}
+
+pub fn save_png<P: AsRef<Path>>(image: &RgbaImage, path: P) -> Result<(), HeadlessError> {
+    let path = path.as_ref();
+    image.save_with_format(path, image::ImageFormat::Png).map_err(|err| HeadlessError::Image(path.to_path_buf(), err))
+}
+
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+pub struct ImageDiff {
+    // Pixels with a channel differing by more than the tolerance
+    pub different_pixels: usize,
+    // Largest channel difference of any pixel
+    pub max_difference: u8,
+    // Average channel difference over the whole image, 0 to 255
+    pub mean_difference: f32
+}
+
+// Channels may differ by `tolerance` before a pixel counts as different
+pub fn compare_images(a: &RgbaImage, b: &RgbaImage, tolerance: u8) -> Result<ImageDiff, HeadlessError> {
+    if a.dimensions() != b.dimensions() {
+        return Err(HeadlessError::SizeMismatch(a.dimensions(), b.dimensions()));
+    }
+
+    let mut diff = ImageDiff::default();
+    let mut total = 0u64;
+    for (pa, pb) in a.pixels().zip(b.pixels()) {
+        let largest = pa.0.iter().zip(pb.0.iter()).map(|(ca, cb)| ca.abs_diff(*cb)).max().unwrap_or(0);
+        total += pa.0.iter().zip(pb.0.iter()).map(|(ca, cb)| ca.abs_diff(*cb) as u64).sum::<u64>();
+
+        diff.max_difference = diff.max_difference.max(largest);
+        if largest > tolerance {
+            diff.different_pixels += 1;
+        }
+    }
+
+    let channels = a.as_raw().len().max(1);
+    diff.mean_difference = total as f32 / channels as f32;
+    Ok(diff)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum GoldenResult {
+    Match(ImageDiff),
+    // The golden image was missing or UPDATE_GOLDEN is set, so it was written from the image
+    Written,
+    // Too far from the golden image, the rendered one is saved at the given path
+    Mismatch(ImageDiff, PathBuf)
+}
+
+impl fmt::Display for GoldenResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GoldenResult::Match(diff) => write!(f, "matches the golden image (max difference {})", diff.max_difference),
+            GoldenResult::Written => write!(f, "golden image written"),
+            GoldenResult::Mismatch(diff, path) => write!(f, "{} pixels differ from the golden image (max difference {}), result saved to {}",
+                diff.different_pixels, diff.max_difference, path.display())
+        }
+    }
+}
+
+// Checks the image against the PNG at `path`. A missing golden image is created from this one,
+// as is every golden image while the UPDATE_GOLDEN environment variable is set. On a mismatch the
+// image is saved next to the golden one as "<name>.actual.png".
+pub fn compare_golden<P: AsRef<Path>>(image: &RgbaImage, path: P, tolerance: u8, max_different_pixels: usize) -> Result<GoldenResult, HeadlessError> {
+    let path = path.as_ref();
+    if !path.exists() || std::env::var_os("UPDATE_GOLDEN").is_some() {
+        save_png(image, path)?;
+        return Ok(GoldenResult::Written);
+    }
+
+    let golden = image::open(path).map_err(|err| HeadlessError::Image(path.to_path_buf(), err))?.to_rgba8();
+    let diff = compare_images(image, &golden, tolerance)?;
+    if diff.different_pixels <= max_different_pixels {
+        return Ok(GoldenResult::Match(diff));
+    }
+
+    let actual = path.with_extension("actual.png");
+    save_png(image, &actual)?;
+    Ok(GoldenResult::Mismatch(diff, actual))
+}
+
+// Set where the rendering tests must run, so a machine without EGL fails them instead of skipping
+#[cfg(test)]
+pub(super) const REQUIRE_EGL_VAR: &str = "DENGINE_REQUIRE_EGL";
+
+// Renderer for a test that needs a GL context. Without any EGL device the test is skipped with a
+// note, or fails when DENGINE_REQUIRE_EGL is set.
+#[cfg(test)]
+pub(super) fn test_renderer(test: &str) -> Option<HeadlessRenderer> {
+    match HeadlessRenderer::new() {
+        Ok(headless) => Some(headless),
+        Err(err) if std::env::var_os(REQUIRE_EGL_VAR).is_some() => panic!("{} needs an EGL device and {} is set: {}", test, REQUIRE_EGL_VAR, err),
+        Err(err) => {
+            println!("Skipping {}, set {} to fail instead: {}", test, REQUIRE_EGL_VAR, err);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::math::Vec3;
+    use super::super::{Cuboid, Object, Teapot};
+
+    fn golden(name: &str) -> PathBuf {
+        PathBuf::from(format!("{}/tests/golden/{}", env!("CARGO_MANIFEST_DIR"), name))
+    }
+
+    #[test]
+    fn images_compare_within_the_tolerance() {
+        let a = RgbaImage::from_pixel(4, 2, image::Rgba([100, 100, 100, 255]));
+        let mut b = a.clone();
+        b.put_pixel(0, 0, image::Rgba([103, 100, 100, 255]));
+        b.put_pixel(1, 0, image::Rgba([100, 110, 100, 255]));
+
+        let diff = compare_images(&a, &b, 5).unwrap();
+        assert_eq!((diff.different_pixels, diff.max_difference), (1, 10));
+        assert!(matches!(compare_images(&a, &RgbaImage::new(2, 2), 5), Err(HeadlessError::SizeMismatch((4, 2), (2, 2)))));
+    }
+
+    #[test]
+    fn small_scene_matches_the_golden_image() {
+        let Some(mut headless) = test_renderer("small_scene_matches_the_golden_image") else {
+            return;
+        };
+
+        let mut world = World::new("Golden");
+        world.add_object(Cuboid {size: Vec3::new(4.0, 0.2, 4.0), ..Cuboid::new("Floor")});
+        let mut teapot = Teapot::new("Teapot");
+        teapot.transform.translation = Vec3::new(0.0, 0.1, 0.0);
+        world.add_object(teapot);
+        world.set_global_light(Vec3::new(-1.0, 2.0, -0.5), Vec3::ONE);
+
+        let mut camera = Camera::new();
+        camera.position = Vec3::new(0.8, 0.8, -1.2);
+        camera.look_at(Vec3::new(0.0, 0.15, 0.0));
+
+        let image = headless.render(&mut world, &camera, 128, 96).unwrap();
+        // Drivers round differently, a few pixels on the edges may flip
+        let result = compare_golden(&image, golden("small_scene.png"), 8, 40).unwrap();
+        assert!(!matches!(result, GoldenResult::Mismatch(..)), "{}", result);
+    }
+}
@@ -1,6 +1,7 @@
-use std::{time::Instant, default};
+use std::collections::HashSet;
+use std::time::Instant;
 
-use winit::event::{Event, WindowEvent, KeyboardInput, VirtualKeyCode, ElementState, };
+use winit::event::{DeviceEvent, Event, WindowEvent, KeyboardInput, MouseButton, MouseScrollDelta, VirtualKeyCode, ElementState, };
 
 pub struct Key {
     last_press: Instant,
@@ -9,31 +10,120 @@ pub struct Key {
 }
 
 impl Key {
-    pub fn new(culldown: f32, virtual_keycode: VirtualKeyCode) -> &'static mut Self {
+    pub fn new(culldown: f32, virtual_keycode: VirtualKeyCode) -> Self {
         let last_press = Instant::now();
         
-        Box::leak(Box::new(Self {last_press, culldown, virtual_keycode}))
+        Self {last_press, culldown, virtual_keycode}
     }
 
     pub fn is_pressed(&mut self, event: &Event<'_, ()>) -> bool {
         if Instant::now().duration_since(self.last_press).as_secs_f32() < self.culldown {
             return false;
         }
+        if let Event::WindowEvent {
+            event: WindowEvent::KeyboardInput {
+                input: KeyboardInput {virtual_keycode, state, ..},
+                ..
+            },
+            ..
+        } = event {
+            if *virtual_keycode == Some(self.virtual_keycode) && *state == ElementState::Pressed  {
+                self.last_press = Instant::now();
+                return true;
+            }
+        }
+        false
+    }
+}
+
+// Keys and mouse buttons currently held, plus mouse movement and scrolling since the last frame
+#[derive(Default)]
+pub struct InputState {
+    keys: HashSet<VirtualKeyCode>,
+    buttons: HashSet<MouseButton>,
+    // Raw device motion, keeps coming when the cursor is grabbed
+    mouse_delta: (f32, f32),
+    // Lines scrolled, positive away from the user
+    scroll: f32
+}
+
+impl InputState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn handle_event(&mut self, event: &Event<'_, ()>) {
         match event {
-            Event::WindowEvent {
-                event: WindowEvent::KeyboardInput {
-                    input: KeyboardInput {virtual_keycode, state, ..},
-                    ..
+            Event::WindowEvent {event, ..} => match event {
+                WindowEvent::KeyboardInput {input: KeyboardInput {virtual_keycode: Some(key), state, ..}, ..} => match state {
+                    ElementState::Pressed => { self.keys.insert(*key); },
+                    ElementState::Released => { self.keys.remove(key); }
                 },
-                ..
-            } => {
-                if *virtual_keycode == Some(self.virtual_keycode.clone()) && *state == ElementState::Pressed  {
-                    self.last_press = Instant::now();
-                    return true;
-                }
+                WindowEvent::MouseInput {button, state, ..} => match state {
+                    ElementState::Pressed => { self.buttons.insert(*button); },
+                    ElementState::Released => { self.buttons.remove(button); }
+                },
+                WindowEvent::MouseWheel {delta, ..} => self.scroll += match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    // Roughly one line per 20 pixels of touchpad scrolling
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / 20.0
+                },
+                // Releases aren't delivered to unfocused windows
+                WindowEvent::Focused(false) => {
+                    self.keys.clear();
+                    self.buttons.clear();
+                },
+                _ => ()
+            },
+            Event::DeviceEvent {event: DeviceEvent::MouseMotion {delta}, ..} => {
+                self.mouse_delta.0 += delta.0 as f32;
+                self.mouse_delta.1 += delta.1 as f32;
             },
             _ => ()
         }
-        return false;
+    }
+
+    pub fn is_key_held(&self, key: VirtualKeyCode) -> bool {
+        self.keys.contains(&key)
+    }
+
+    pub fn is_button_held(&self, button: MouseButton) -> bool {
+        self.buttons.contains(&button)
+    }
+
+    // Pixels moved since the last `end_frame`, y grows downwards
+    pub fn get_mouse_delta(&self) -> (f32, f32) {
+        self.mouse_delta
+    }
+
+    pub fn get_scroll(&self) -> f32 {
+        self.scroll
+    }
+
+    // Call once per frame after everything read the deltas
+    pub fn end_frame(&mut self) {
+        self.mouse_delta = (0.0, 0.0);
+        self.scroll = 0.0;
+    }
+}
+
+// Controllers are tested without a window sending events
+#[cfg(test)]
+impl InputState {
+    pub(super) fn hold_key(&mut self, key: VirtualKeyCode) {
+        self.keys.insert(key);
+    }
+
+    pub(super) fn hold_button(&mut self, button: MouseButton) {
+        self.buttons.insert(button);
+    }
+
+    pub(super) fn move_mouse(&mut self, dx: f32, dy: f32) {
+        self.mouse_delta.0 += dx;
+        self.mouse_delta.1 += dy;
+    }
+
+    pub(super) fn scroll(&mut self, lines: f32) {
+        self.scroll += lines;
     }
 }
@@ -0,0 +1,182 @@
+// Light sources placed in the world like any other object
+use serde::{Serialize, Deserialize};
+
+use super::math::{Mat4, Vec3};
+use super::{Object, Transform, World};
+
+// Most lights the shaders take, the rest are dropped (see RenderStats)
+pub const MAX_LIGHTS: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum LightKind {
+    // Parallel rays along the forward axis, position is ignored
+    Directional,
+    Point,
+    // Cone around the forward axis, half-angles in radians; full intensity inside `inner_angle`
+    Spot {inner_angle: f32, outer_angle: f32}
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Light {
+    name: String,
+
+    pub transform: Transform,
+    pub kind: LightKind,
+    pub color: Vec3,
+    pub intensity: f32,
+    // Constant, linear and quadratic falloff with distance, unused by directional lights
+    pub attenuation: Vec3
+}
+
+impl Default for Light {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            transform: Transform::IDENTITY,
+            kind: LightKind::Point,
+            color: Vec3::ONE,
+            intensity: 1.0,
+            attenuation: Vec3::new(1.0, 0.09, 0.032)
+        }
+    }
+}
+
+impl Light {
+    pub fn directional(name: &str, direction: Vec3) -> Self {
+        let mut light = Self {kind: LightKind::Directional, ..Self::new(name)};
+        light.transform.look_to(direction);
+        light
+    }
+
+    pub fn point(name: &str, position: Vec3) -> Self {
+        let mut light = Self::new(name);
+        light.transform.translation = position;
+        light
+    }
+
+    // Angles in radians
+    pub fn spot(name: &str, position: Vec3, direction: Vec3, inner_angle: f32, outer_angle: f32) -> Self {
+        let mut light = Self {kind: LightKind::Spot {inner_angle, outer_angle}, ..Self::new(name)};
+        light.transform.translation = position;
+        light.transform.look_to(direction);
+        light
+    }
+}
+
+#[typetag::serde]
+impl Object for Light {
+    fn new(name: &str) -> Self where Self: Sized {
+        let name = name.to_string();
+        Self {name, ..Default::default()}
+    }
+
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn transform(&self) -> &Transform {
+        &self.transform
+    }
+
+    fn transform_mut(&mut self) -> &mut Transform {
+        &mut self.transform
+    }
+}
+
+// One light as the shaders see it, in view space
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LightData {
+    pub kind: i32,
+    pub position: [f32; 3],
+    pub direction: [f32; 3],
+    // Already multiplied by the intensity
+    pub color: [f32; 3],
+    pub attenuation: [f32; 3],
+    // Cosines of the cone angles, both 0 for other kinds
+    pub inner_cos: f32,
+    pub outer_cos: f32
+}
+
+impl LightData {
+    fn new(light: &Light, world_matrix: Mat4, view: Mat4) -> Self {
+        let position = view.transform_point(world_matrix.transform_point(Vec3::ZERO));
+        let direction = view.transform_vector(world_matrix.transform_vector(Vec3::Z)).normalize();
+
+        let (kind, inner_cos, outer_cos) = match light.kind {
+            LightKind::Directional => (0, 0.0, 0.0),
+            LightKind::Point => (1, 0.0, 0.0),
+            LightKind::Spot {inner_angle, outer_angle} => (2, inner_angle.cos(), outer_angle.cos())
+        };
+
+        Self {
+            kind,
+            position: position.get_matrix(),
+            direction: direction.get_matrix(),
+            color: (light.color * light.intensity).get_matrix(),
+            attenuation: light.attenuation.get_matrix(),
+            inner_cos,
+            outer_cos
+        }
+    }
+}
+
+impl World {
+    // Directional light every world has besides its light objects. `direction` points
+    // towards the light, a black color turns it off.
+    pub fn set_global_light(&mut self, direction: Vec3, color: Vec3) {
+        self.global_light = direction;
+        self.global_light_color = color;
+    }
+
+    // Direction towards the light and its color
+    pub fn get_global_light(&self) -> (Vec3, Vec3) {
+        (self.global_light, self.global_light_color)
+    }
+
+    // The global light as a light object, None while it is off
+    pub(super) fn get_global_light_object(&self) -> Option<Light> {
+        if self.global_light_color == Vec3::ZERO {
+            return None;
+        }
+
+        let mut light = Light::directional("Global Light", -self.global_light);
+        light.color = self.global_light_color;
+        Some(light)
+    }
+
+    // The global light first, then every light object in slot order, as of the last `update_transforms`
+    pub fn collect_lights(&self, view: Mat4) -> Vec<LightData> {
+        let global = self.get_global_light_object().map(|light| LightData::new(&light, light.transform.get_matrix(), view));
+
+        let objects = self.get_nodes().filter_map(|(_, node)| {
+            let light = node.get_object().as_any().downcast_ref::<Light>()?;
+            Some(LightData::new(light, node.get_world_matrix(), view))
+        });
+        global.into_iter().chain(objects).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn global_light_comes_first() {
+        let mut world = World::new("Lights");
+        world.add_object(Light::point("Lamp", Vec3::new(0.0, 2.0, 0.0)));
+        world.add_object(Light::directional("Sun", -Vec3::Y));
+        world.set_global_light(Vec3::new(0.0, 0.0, -2.0), Vec3::new(1.0, 0.5, 0.0));
+        world.update_transforms();
+
+        let lights = world.collect_lights(Mat4::IDENTITY);
+        assert_eq!(lights.iter().map(|light| light.kind).collect::<Vec<i32>>(), [0, 1, 0]);
+        // Rays travel away from the light
+        assert_eq!(lights[0].direction, [0.0, 0.0, 1.0]);
+        assert_eq!(lights[0].color, [1.0, 0.5, 0.0]);
+        assert_eq!(lights[1].position, [0.0, 2.0, 0.0]);
+
+        world.set_global_light(Vec3::Y, Vec3::ZERO);
+        assert_eq!(world.collect_lights(Mat4::IDENTITY).len(), 2);
+        assert_eq!(world.get_global_light(), (Vec3::Y, Vec3::ZERO));
+    }
+}
@@ -1,24 +1,142 @@
-mod engine;
-use engine::{Engine, Cuboid, World, Vec3, Object, Teapot, radians};
+use dengine as engine;
+use engine::{Engine, World, Vec3, Quat, Object, Teapot, Cuboid, Material, Transform, radians};
+use engine::light::Light;
+use engine::primitives::Plane;
+use engine::view::{SceneCamera, Viewport};
+use engine::Projection;
+use engine::headless::{HeadlessRenderer, save_png};
 
 fn main() {
-    let engine = Engine::new();
+    let args: Vec<String> = std::env::args().collect();
 
-    engine.camera.set_fov(80.0);
-    engine.camera.position = Vec3::new(2.0, 1.0, 1.0);
-    engine.camera.direction = Vec3::new(radians(10.0), radians(-200.0), radians(180.0));
+    let mut engine = Engine::new();
 
-    let main_world = World::new("Test World");
+    // Benchmark: `--bench N` draws an N x N teapot grid, `--no-instancing` starts with one draw call per teapot
+    let a = match args.iter().position(|arg| arg == "--bench") {
+        Some(i) => args.get(i + 1).and_then(|n| n.parse().ok()).unwrap_or(50),
+        None => 10
+    };
+    if args.iter().any(|arg| arg == "--no-instancing") {
+        engine.settings.set_instancing(false);
+    }
+    // `--record` dumps every frame from the start, F10 toggles it at runtime and F12 takes a screenshot
+    if args.iter().any(|arg| arg == "--record") {
+        engine.settings.set_record_on_start(true);
+    }
+
+    let mut main_world = World::new("Test World");
+
+    main_world.camera.set_fov(80.0);
+    // Behind the first teapots, looking across the grid. Tab grabs the mouse, WASD/QE fly,
+    // C switches between fly, orbit and fixed cameras and F orbits the selected teapot.
+    main_world.camera.position = Vec3::new(0.0, 2.5, -1.0);
+    main_world.camera.look_at(Vec3::new(a as f32 * 0.75, 0.0, a as f32 * 0.5));
+
+    // One color per column, so each column is still a single instanced batch
+    let palette = [
+        Vec3::new(0.9, 0.1, 0.1),
+        Vec3::new(0.9, 0.6, 0.1),
+        Vec3::new(0.2, 0.8, 0.2),
+        Vec3::new(0.1, 0.5, 0.9),
+        Vec3::new(0.6, 0.2, 0.9)
+    ];
 
-    let a = 10;
     for x in 1..a+1 {
         for z in 1..a+1 {
-            let teapot = Teapot::new(Box::leak(format!("Teapot{}", x+x*z).into_boxed_str()));
-            teapot.position = Vec3::new((x * 150) as f32, 0.0, (z * 100) as f32);
-            main_world.add_object(teapot);
+            let mut teapot = Teapot::new(&format!("Teapot{}", x+x*z));
+            teapot.transform.translation = Vec3::new(x as f32 * 1.5, 0.0, z as f32);
+            teapot.transform.set_euler(radians((x * 36) as f32), 0.0, 0.0);
+            teapot.material = Material::from_color(palette[x % palette.len()]);
+            let id = main_world.add_object(teapot);
+            if x == 1 && z == 1 {
+                main_world.select(Some(id));
+            }
+        }
+    }
+
+    // Floor under the grid, its top just below y = 0 so the debug grid stays visible
+    let mut floor = Cuboid::new("Floor");
+    floor.size = Vec3::new(a as f32 * 1.5 + 1.5, 0.1, a as f32 + 1.0);
+    floor.material = Material::from_color(Vec3::splat(0.3));
+    floor.material.specular = Vec3::ZERO;
+    floor.transform.translation = Vec3::new((a + 1) as f32 * 0.75, -0.06, (a + 1) as f32 * 0.5);
+    main_world.add_object(floor);
+
+    // Sun as the global light, two colored lamps over the grid and a spot on the first teapot
+    main_world.set_ambient_color((0.1, 0.1, 0.12, 1.0));
+    main_world.set_global_light(Vec3::new(-1.0, 0.4, 0.9), Vec3::ONE);
+
+    let mut lamp = Light::point("Blue Lamp", Vec3::new(3.0, 1.5, 2.0));
+    lamp.color = Vec3::new(0.3, 0.5, 1.0);
+    main_world.add_object(lamp);
+
+    let mut lamp = Light::point("Red Lamp", Vec3::new(a as f32, 1.5, a as f32 * 0.5));
+    lamp.color = Vec3::new(1.0, 0.3, 0.2);
+    main_world.add_object(lamp);
+
+    let mut spot = Light::spot("Spot", Vec3::new(1.5, 3.0, 1.0), -Vec3::Y, radians(15.0), radians(25.0));
+    spot.intensity = 2.0;
+    main_world.add_object(spot);
+
+    // Top-down map of the grid in the top-right corner
+    let mut minimap = SceneCamera::viewport("Minimap", Viewport::new(0.75, 0.0, 0.25, 0.25));
+    minimap.camera.set_projection(Projection::Orthographic {size: a as f32 + 2.0});
+    minimap.transform.translation = Vec3::new((a + 1) as f32 * 0.75, 20.0, (a + 1) as f32 * 0.5);
+    minimap.transform.look_to(-Vec3::Y);
+    main_world.add_object(minimap);
+
+    // Security camera watching the first teapots, shown on a screen behind the grid
+    let mut security = SceneCamera::texture("Security Camera", "monitor", 512, 288);
+    security.transform.translation = Vec3::new(-1.0, 2.0, -1.0);
+    security.transform.look_to(Vec3::new(3.0, -1.5, 3.0));
+    main_world.add_object(security);
+
+    let mut monitor = Plane::new("Monitor");
+    monitor.size = engine::math::Vec2::new(4.0, 2.25);
+    monitor.transform.translation = Vec3::new((a + 1) as f32 * 0.75, 1.5, a as f32 + 1.5);
+    monitor.transform.rotation = Quat::from_axis_angle(Vec3::X, radians(-90.0));
+    monitor.material = Material::from_color(Vec3::ONE);
+    monitor.material.diffuse_texture = Some("monitor".to_string());
+    monitor.material.specular = Vec3::ZERO;
+    main_world.add_object(monitor);
+
+    // `--load model.obj` (or .gltf / .glb) puts the model next to the first teapots and selects it
+    if let Some(path) = args.iter().position(|arg| arg == "--load").and_then(|i| args.get(i + 1)) {
+        let result = match path.rsplit('.').next().map(|ext| ext.to_lowercase()).as_deref() {
+            Some("gltf") | Some("glb") => main_world.load_gltf(path).map_err(|err| err.to_string()),
+            _ => main_world.load_obj(path).map_err(|err| err.to_string())
+        };
+        match result {
+            Ok(id) => {
+                main_world.set_local_transform(id, Transform::from_translation(Vec3::new(-1.0, 0.5, 2.0)));
+                main_world.select(Some(id));
+            },
+            Err(err) => println!("Can't load {}: {}", path, err)
         }
     }
 
+    // `--headless out.png` renders one 1280x720 frame to the file instead of opening a window
+    if let Some(path) = args.iter().position(|arg| arg == "--headless").and_then(|i| args.get(i + 1)) {
+        let result = HeadlessRenderer::new().and_then(|mut headless| {
+            let camera = main_world.camera.clone();
+            let image = headless.render(&mut main_world, &camera, 1280, 720)?;
+            save_png(&image, path)
+        });
+        match result {
+            Ok(()) => println!("Saved {}", path),
+            Err(err) => println!("Can't render headless: {}", err)
+        }
+        return;
+    }
+
     engine.set_world(Some(main_world));
+
+    // Spin every teapot around its vertical axis
+    engine.set_update(|world, delta| {
+        for (_, teapot) in world.objects_of_type_mut::<Teapot>() {
+            teapot.transform.rotate(Quat::from_axis_angle(Vec3::Y, radians(45.0) * delta));
+        }
+    });
+
     engine.run();
 }
\ No newline at end of file
@@ -0,0 +1,213 @@
+// Surface parameters of an object, turned into shader uniforms by the renderer
+use std::collections::BTreeMap;
+
+use serde::{Serialize, Deserialize};
+
+use super::gltf_import::PbrMaterial;
+use super::math::{Vec3, Vec4};
+use super::obj::ObjMaterial;
+use super::texture::Sampler;
+
+// Value of a custom uniform
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Uniform {
+    Float(f32),
+    Int(i32),
+    Bool(bool),
+    Vec2([f32; 2]),
+    Vec3([f32; 3]),
+    Vec4([f32; 4]),
+    Mat4([[f32; 4]; 4])
+}
+
+impl Uniform {
+    pub fn get_value(&self) -> glium::uniforms::UniformValue<'static> {
+        use glium::uniforms::UniformValue;
+
+        match *self {
+            Uniform::Float(value) => UniformValue::Float(value),
+            Uniform::Int(value) => UniformValue::SignedInt(value),
+            Uniform::Bool(value) => UniformValue::Bool(value),
+            Uniform::Vec2(value) => UniformValue::Vec2(value),
+            Uniform::Vec3(value) => UniformValue::Vec3(value),
+            Uniform::Vec4(value) => UniformValue::Vec4(value),
+            Uniform::Mat4(value) => UniformValue::Mat4(value)
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Material {
+    // Linear RGBA
+    pub base_color: Vec4,
+    pub specular: Vec3,
+    pub shininess: f32,
+
+    // PNG or JPEG paths, or names of camera render targets (see view::ViewTarget); diffuse and
+    // specular maps multiply the colors above, normal maps are tangent space with the tangents derived per pixel
+    pub diffuse_texture: Option<String>,
+    pub specular_texture: Option<String>,
+    pub normal_texture: Option<String>,
+    pub sampler: Sampler,
+
+    // Extra uniforms passed to the shader by name, ignored if it has no such uniform
+    pub uniforms: BTreeMap<String, Uniform>
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            base_color: Vec4::new(0.8, 0.8, 0.8, 1.0),
+            specular: Vec3::splat(0.5),
+            shininess: 32.0,
+            diffuse_texture: None,
+            specular_texture: None,
+            normal_texture: None,
+            sampler: Sampler::default(),
+            uniforms: BTreeMap::new()
+        }
+    }
+}
+
+impl Material {
+    // Opaque material of the given color
+    pub fn from_color(color: Vec3) -> Self {
+        Self {base_color: color.extend(1.0), ..Default::default()}
+    }
+
+    pub fn set_uniform(&mut self, name: &str, value: Uniform) {
+        self.uniforms.insert(name.to_string(), value);
+    }
+
+    pub fn get_uniform(&self, name: &str) -> Option<Uniform> {
+        self.uniforms.get(name).copied()
+    }
+}
+
+impl From<&ObjMaterial> for Material {
+    fn from(material: &ObjMaterial) -> Self {
+        let [r, g, b] = material.diffuse;
+        let path = |path: &Option<std::path::PathBuf>| path.as_ref().map(|path| path.to_string_lossy().into_owned());
+
+        Self {
+            base_color: Vec4::new(r, g, b, material.opacity),
+            specular: Vec3::from_matrix(material.specular),
+            shininess: material.shininess,
+            diffuse_texture: path(&material.diffuse_map),
+            specular_texture: path(&material.specular_map),
+            normal_texture: path(&material.normal_map),
+            sampler: Sampler::default(),
+            uniforms: BTreeMap::new()
+        }
+    }
+}
+
+// Metallic-roughness approximated with Blinn-Phong terms
+impl From<&PbrMaterial> for Material {
+    fn from(material: &PbrMaterial) -> Self {
+        let [r, g, b, a] = material.base_color;
+        let base = Vec3::new(r, g, b);
+
+        // Dielectrics reflect about 4%, metals reflect their own color
+        let specular = Vec3::splat(0.04).lerp(base, material.metallic);
+        let alpha = (material.roughness * material.roughness).max(0.01);
+        let shininess = (2.0 / (alpha * alpha) - 2.0).clamp(1.0, 256.0);
+
+        Self {
+            base_color: Vec4::new(r, g, b, a),
+            specular,
+            shininess,
+            diffuse_texture: material.base_color_texture.as_ref().and_then(|texture| texture.uri.clone()),
+            specular_texture: None,
+            normal_texture: material.normal_texture.as_ref().and_then(|texture| texture.uri.clone()),
+            sampler: Sampler::default(),
+            uniforms: BTreeMap::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::gltf_import::TextureRef;
+    use super::super::{Cuboid, Object, World};
+
+    #[test]
+    fn obj_materials_keep_their_colors_and_maps() {
+        let obj = ObjMaterial {
+            name: "Brick".to_string(),
+            ambient: [0.1, 0.1, 0.1],
+            diffuse: [0.6, 0.3, 0.2],
+            specular: [0.2, 0.2, 0.2],
+            shininess: 16.0,
+            opacity: 0.5,
+            diffuse_map: Some("maps/brick.png".into()),
+            specular_map: None,
+            normal_map: Some("maps/brick_normal.png".into())
+        };
+
+        let material = Material::from(&obj);
+        assert_eq!(material.base_color, Vec4::new(0.6, 0.3, 0.2, 0.5));
+        assert_eq!(material.specular, Vec3::splat(0.2));
+        assert_eq!(material.shininess, 16.0);
+        assert_eq!(material.diffuse_texture.as_deref(), Some("maps/brick.png"));
+        assert_eq!(material.specular_texture, None);
+        assert_eq!(material.normal_texture.as_deref(), Some("maps/brick_normal.png"));
+    }
+
+    #[test]
+    fn pbr_materials_become_blinn_phong() {
+        let metal = PbrMaterial {
+            base_color: [1.0, 0.5, 0.0, 1.0],
+            metallic: 1.0,
+            roughness: 0.0,
+            base_color_texture: Some(TextureRef {image: 0, uri: Some("color.png".to_string())}),
+            normal_texture: Some(TextureRef {image: 1, uri: None}),
+            ..Default::default()
+        };
+        let material = Material::from(&metal);
+        assert_eq!(material.base_color, Vec4::new(1.0, 0.5, 0.0, 1.0));
+        // Metals reflect their own color, and smooth ones get the sharpest highlight
+        assert_eq!(material.specular, Vec3::new(1.0, 0.5, 0.0));
+        assert_eq!(material.shininess, 256.0);
+        assert_eq!(material.diffuse_texture.as_deref(), Some("color.png"));
+        assert_eq!(material.normal_texture, None);
+
+        let plastic = Material::from(&PbrMaterial {metallic: 0.0, roughness: 1.0, ..Default::default()});
+        assert!((plastic.specular - Vec3::splat(0.04)).length() < 1e-6);
+        assert_eq!(plastic.shininess, 1.0);
+    }
+
+    #[test]
+    fn uniforms_compare_regardless_of_order() {
+        let mut a = Material::default();
+        a.set_uniform("time", Uniform::Float(1.0));
+        a.set_uniform("tint", Uniform::Vec3([1.0, 0.0, 0.0]));
+        let mut b = Material::default();
+        b.set_uniform("tint", Uniform::Vec3([1.0, 0.0, 0.0]));
+        b.set_uniform("time", Uniform::Float(1.0));
+
+        assert_eq!(a, b);
+        assert_eq!(a.uniforms.keys().collect::<Vec<_>>(), ["time", "tint"]);
+        assert_eq!(a.get_uniform("time"), Some(Uniform::Float(1.0)));
+
+        b.set_uniform("time", Uniform::Float(2.0));
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn different_materials_are_batched_apart() {
+        let mut world = World::new("Materials");
+        for (name, color) in [("Red", Vec3::X), ("Also Red", Vec3::X), ("Green", Vec3::Y)] {
+            world.add_object(Cuboid {material: Material::from_color(color), ..Cuboid::new(name)});
+        }
+
+        let batches = world.collect_batches();
+        assert_eq!(batches.len(), 2);
+        let mut sizes: Vec<usize> = batches.iter().map(|batch| batch.models.len()).collect();
+        sizes.sort();
+        assert_eq!(sizes, [1, 2]);
+        assert_eq!(batches[0].mesh, batches[1].mesh);
+    }
+}
@@ -0,0 +1,992 @@
+use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
+
+use serde::{Serialize, Deserialize};
+
+// Degrees -> Radians
+pub fn radians(x: f32) -> f32 {
+    x.to_radians()
+}
+
+// Component-wise operators shared by all vector types
+macro_rules! impl_vector_ops {
+    ($name:ident { $($field:ident),+ }) => {
+        impl Add for $name {
+            type Output = Self;
+
+            fn add(self, rhs: Self) -> Self::Output {
+                Self { $($field: self.$field + rhs.$field),+ }
+            }
+        }
+
+        impl Sub for $name {
+            type Output = Self;
+
+            fn sub(self, rhs: Self) -> Self::Output {
+                Self { $($field: self.$field - rhs.$field),+ }
+            }
+        }
+
+        // Component-wise product
+        impl Mul for $name {
+            type Output = Self;
+
+            fn mul(self, rhs: Self) -> Self::Output {
+                Self { $($field: self.$field * rhs.$field),+ }
+            }
+        }
+
+        impl Div for $name {
+            type Output = Self;
+
+            fn div(self, rhs: Self) -> Self::Output {
+                Self { $($field: self.$field / rhs.$field),+ }
+            }
+        }
+
+        impl Mul<f32> for $name {
+            type Output = Self;
+
+            fn mul(self, rhs: f32) -> Self::Output {
+                Self { $($field: self.$field * rhs),+ }
+            }
+        }
+
+        impl Mul<$name> for f32 {
+            type Output = $name;
+
+            fn mul(self, rhs: $name) -> Self::Output {
+                rhs * self
+            }
+        }
+
+        impl Div<f32> for $name {
+            type Output = Self;
+
+            fn div(self, rhs: f32) -> Self::Output {
+                Self { $($field: self.$field / rhs),+ }
+            }
+        }
+
+        impl Neg for $name {
+            type Output = Self;
+
+            fn neg(self) -> Self::Output {
+                Self { $($field: -self.$field),+ }
+            }
+        }
+
+        impl AddAssign for $name {
+            fn add_assign(&mut self, rhs: Self) {
+                $(self.$field += rhs.$field;)+
+            }
+        }
+
+        impl SubAssign for $name {
+            fn sub_assign(&mut self, rhs: Self) {
+                $(self.$field -= rhs.$field;)+
+            }
+        }
+
+        impl MulAssign<f32> for $name {
+            fn mul_assign(&mut self, rhs: f32) {
+                $(self.$field *= rhs;)+
+            }
+        }
+
+        impl DivAssign<f32> for $name {
+            fn div_assign(&mut self, rhs: f32) {
+                $(self.$field /= rhs;)+
+            }
+        }
+
+        impl $name {
+            pub fn splat(value: f32) -> Self {
+                Self { $($field: value),+ }
+            }
+
+            pub fn dot(&self, rhs: Self) -> f32 {
+                0.0 $(+ self.$field * rhs.$field)+
+            }
+
+            pub fn length_squared(&self) -> f32 {
+                self.dot(*self)
+            }
+
+            pub fn length(&self) -> f32 {
+                self.length_squared().sqrt()
+            }
+
+            pub fn distance(&self, rhs: Self) -> f32 {
+                (*self - rhs).length()
+            }
+
+            // Zero vector stays zero instead of turning into NaN
+            pub fn normalize(&self) -> Self {
+                let len = self.length();
+                match len > 0.0 {
+                    true => *self / len,
+                    false => *self
+                }
+            }
+
+            pub fn lerp(&self, rhs: Self, t: f32) -> Self {
+                *self + (rhs - *self) * t
+            }
+
+            pub fn min(&self, rhs: Self) -> Self {
+                Self { $($field: self.$field.min(rhs.$field)),+ }
+            }
+
+            pub fn max(&self, rhs: Self) -> Self {
+                Self { $($field: self.$field.max(rhs.$field)),+ }
+            }
+
+            pub fn abs(&self) -> Self {
+                Self { $($field: self.$field.abs()),+ }
+            }
+
+            pub fn approx_eq(&self, rhs: Self, epsilon: f32) -> bool {
+                true $(&& (self.$field - rhs.$field).abs() <= epsilon)+
+            }
+        }
+    };
+}
+
+#[derive(Clone, Copy, Default, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Vec2 {
+    pub x: f32,
+    pub y: f32
+}
+
+impl Vec2 {
+    pub const ZERO: Self = Self {x: 0.0, y: 0.0};
+    pub const ONE: Self = Self {x: 1.0, y: 1.0};
+
+    pub fn new(x: f32, y: f32) -> Self {
+        Self {x, y}
+    }
+
+    pub fn get_matrix(&self) -> [f32; 2] {
+        [self.x, self.y]
+    }
+
+    pub fn from_matrix(matrix: [f32; 2]) -> Self {
+        Self {x: matrix[0], y: matrix[1]}
+    }
+}
+
+impl_vector_ops!(Vec2 { x, y });
+
+#[derive(Clone, Copy, Default, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Vec3 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32
+}
+
+impl Vec3 {
+    pub const ZERO: Self = Self {x: 0.0, y: 0.0, z: 0.0};
+    pub const ONE: Self = Self {x: 1.0, y: 1.0, z: 1.0};
+    pub const X: Self = Self {x: 1.0, y: 0.0, z: 0.0};
+    pub const Y: Self = Self {x: 0.0, y: 1.0, z: 0.0};
+    pub const Z: Self = Self {x: 0.0, y: 0.0, z: 1.0};
+
+    pub fn new(x: f32, y: f32, z: f32) -> Self {
+        Self {x, y, z}
+    }
+
+    pub fn get_tuple(&self) -> (f32, f32, f32) {
+        (self.x, self.y, self.z)
+    }
+
+    pub fn get_matrix(&self) -> [f32; 3] {
+        [self.x, self.y, self.z]
+    }
+
+    pub fn from_matrix(matrix: [f32; 3]) -> Self {
+        Self {x: matrix[0], y: matrix[1], z: matrix[2]}
+    }
+
+    pub fn extend(&self, w: f32) -> Vec4 {
+        Vec4::new(self.x, self.y, self.z, w)
+    }
+
+    pub fn cross(&self, rhs: Self) -> Self {
+        Self {
+            x: self.y * rhs.z - self.z * rhs.y,
+            y: self.z * rhs.x - self.x * rhs.z,
+            z: self.x * rhs.y - self.y * rhs.x
+        }
+    }
+
+    pub fn sin(&self) -> Self {
+        Self::new(self.x.sin(), self.y.sin(), self.z.sin())
+    }
+}
+
+impl_vector_ops!(Vec3 { x, y, z });
+
+#[derive(Clone, Copy, Default, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Vec4 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub w: f32
+}
+
+impl Vec4 {
+    pub const ZERO: Self = Self {x: 0.0, y: 0.0, z: 0.0, w: 0.0};
+    pub const ONE: Self = Self {x: 1.0, y: 1.0, z: 1.0, w: 1.0};
+
+    pub fn new(x: f32, y: f32, z: f32, w: f32) -> Self {
+        Self {x, y, z, w}
+    }
+
+    pub fn get_matrix(&self) -> [f32; 4] {
+        [self.x, self.y, self.z, self.w]
+    }
+
+    pub fn from_matrix(matrix: [f32; 4]) -> Self {
+        Self {x: matrix[0], y: matrix[1], z: matrix[2], w: matrix[3]}
+    }
+
+    pub fn truncate(&self) -> Vec3 {
+        Vec3::new(self.x, self.y, self.z)
+    }
+}
+
+impl_vector_ops!(Vec4 { x, y, z, w });
+
+// Column-major 3x3 matrix: cols[column][row]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Mat3 {
+    pub cols: [[f32; 3]; 3]
+}
+
+impl Default for Mat3 {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Mat3 {
+    pub const IDENTITY: Self = Self {cols: [
+        [1.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0],
+        [0.0, 0.0, 1.0]
+    ]};
+
+    pub fn from_cols(x: Vec3, y: Vec3, z: Vec3) -> Self {
+        Self {cols: [x.get_matrix(), y.get_matrix(), z.get_matrix()]}
+    }
+
+    pub fn col(&self, index: usize) -> Vec3 {
+        Vec3::from_matrix(self.cols[index])
+    }
+
+    pub fn get_matrix(&self) -> [[f32; 3]; 3] {
+        self.cols
+    }
+
+    pub fn from_quat(q: Quat) -> Self {
+        Self::from_cols(q * Vec3::X, q * Vec3::Y, q * Vec3::Z)
+    }
+
+    pub fn scale(scale: Vec3) -> Self {
+        Self::from_cols(Vec3::X * scale.x, Vec3::Y * scale.y, Vec3::Z * scale.z)
+    }
+
+    pub fn transpose(&self) -> Self {
+        let c = self.cols;
+        Self {cols: [
+            [c[0][0], c[1][0], c[2][0]],
+            [c[0][1], c[1][1], c[2][1]],
+            [c[0][2], c[1][2], c[2][2]]
+        ]}
+    }
+
+    pub fn determinant(&self) -> f32 {
+        self.col(0).dot(self.col(1).cross(self.col(2)))
+    }
+
+    pub fn inverse(&self) -> Option<Self> {
+        let det = self.determinant();
+        if det.abs() <= f32::EPSILON {
+            return None;
+        }
+
+        let (a, b, c) = (self.col(0), self.col(1), self.col(2));
+        let rows = Self::from_cols(b.cross(c), c.cross(a), a.cross(b));
+
+        Some(rows.transpose().mul_f32(1.0 / det))
+    }
+
+    fn mul_f32(&self, a: f32) -> Self {
+        Self::from_cols(self.col(0) * a, self.col(1) * a, self.col(2) * a)
+    }
+}
+
+impl Mul for Mat3 {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        Self::from_cols(self * rhs.col(0), self * rhs.col(1), self * rhs.col(2))
+    }
+}
+
+impl Mul<Vec3> for Mat3 {
+    type Output = Vec3;
+
+    fn mul(self, rhs: Vec3) -> Self::Output {
+        self.col(0) * rhs.x + self.col(1) * rhs.y + self.col(2) * rhs.z
+    }
+}
+
+// Column-major 4x4 matrix, same layout glium expects for uniforms
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Mat4 {
+    pub cols: [[f32; 4]; 4]
+}
+
+impl Default for Mat4 {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Mat4 {
+    pub const IDENTITY: Self = Self {cols: [
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [0.0, 0.0, 0.0, 1.0]
+    ]};
+
+    pub fn from_cols(x: Vec4, y: Vec4, z: Vec4, w: Vec4) -> Self {
+        Self {cols: [x.get_matrix(), y.get_matrix(), z.get_matrix(), w.get_matrix()]}
+    }
+
+    pub fn from_mat3(m: Mat3) -> Self {
+        Self::from_cols(m.col(0).extend(0.0), m.col(1).extend(0.0), m.col(2).extend(0.0), Vec4::new(0.0, 0.0, 0.0, 1.0))
+    }
+
+    pub fn col(&self, index: usize) -> Vec4 {
+        Vec4::from_matrix(self.cols[index])
+    }
+
+    pub fn get_matrix(&self) -> [[f32; 4]; 4] {
+        self.cols
+    }
+
+    pub fn to_mat3(self) -> Mat3 {
+        Mat3::from_cols(self.col(0).truncate(), self.col(1).truncate(), self.col(2).truncate())
+    }
+
+    pub fn translation(offset: Vec3) -> Self {
+        let mut m = Self::IDENTITY;
+        m.cols[3] = offset.extend(1.0).get_matrix();
+        m
+    }
+
+    pub fn scale(scale: Vec3) -> Self {
+        Self::from_mat3(Mat3::scale(scale))
+    }
+
+    pub fn rotation(rotation: Quat) -> Self {
+        Self::from_mat3(Mat3::from_quat(rotation))
+    }
+
+    pub fn rotation_x(angle: f32) -> Self {
+        Self::rotation(Quat::from_axis_angle(Vec3::X, angle))
+    }
+
+    pub fn rotation_y(angle: f32) -> Self {
+        Self::rotation(Quat::from_axis_angle(Vec3::Y, angle))
+    }
+
+    pub fn rotation_z(angle: f32) -> Self {
+        Self::rotation(Quat::from_axis_angle(Vec3::Z, angle))
+    }
+
+    // Translation * Rotation * Scale
+    pub fn from_trs(translation: Vec3, rotation: Quat, scale: Vec3) -> Self {
+        let r = Mat3::from_quat(rotation);
+        Self::from_cols(
+            (r.col(0) * scale.x).extend(0.0),
+            (r.col(1) * scale.y).extend(0.0),
+            (r.col(2) * scale.z).extend(0.0),
+            translation.extend(1.0)
+        )
+    }
+
+    // Left-handed perspective (+Z into the screen), depth mapped to [-1; 1]
+    pub fn perspective(fov: f32, aspect_ratio: f32, znear: f32, zfar: f32) -> Self {
+        let f = 1.0 / (fov / 2.0).tan();
+
+        Self {cols: [
+            [f / aspect_ratio, 0.0, 0.0, 0.0],
+            [0.0, f, 0.0, 0.0],
+            [0.0, 0.0, (zfar + znear) / (zfar - znear), 1.0],
+            [0.0, 0.0, -(2.0 * zfar * znear) / (zfar - znear), 0.0]
+        ]}
+    }
+
+    // `perspective` with the far plane at infinity, only the near plane clips
+    pub fn perspective_infinite(fov: f32, aspect_ratio: f32, znear: f32) -> Self {
+        let f = 1.0 / (fov / 2.0).tan();
+
+        Self {cols: [
+            [f / aspect_ratio, 0.0, 0.0, 0.0],
+            [0.0, f, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 1.0],
+            [0.0, 0.0, -2.0 * znear, 0.0]
+        ]}
+    }
+
+    // Left-handed box projection, depth mapped to [-1; 1] like `perspective`
+    pub fn orthographic(left: f32, right: f32, bottom: f32, top: f32, znear: f32, zfar: f32) -> Self {
+        Self {cols: [
+            [2.0 / (right - left), 0.0, 0.0, 0.0],
+            [0.0, 2.0 / (top - bottom), 0.0, 0.0],
+            [0.0, 0.0, 2.0 / (zfar - znear), 0.0],
+            [-(right + left) / (right - left), -(top + bottom) / (top - bottom), -(zfar + znear) / (zfar - znear), 1.0]
+        ]}
+    }
+
+    // Left-handed view matrix looking along `direction`
+    pub fn look_to(position: Vec3, direction: Vec3, up: Vec3) -> Self {
+        let f = direction.normalize();
+        let s = up.cross(f).normalize();
+        let u = f.cross(s);
+
+        Self {cols: [
+            [s.x, u.x, f.x, 0.0],
+            [s.y, u.y, f.y, 0.0],
+            [s.z, u.z, f.z, 0.0],
+            [-position.dot(s), -position.dot(u), -position.dot(f), 1.0]
+        ]}
+    }
+
+    pub fn look_at(position: Vec3, target: Vec3, up: Vec3) -> Self {
+        Self::look_to(position, target - position, up)
+    }
+
+    pub fn transpose(&self) -> Self {
+        let c = self.cols;
+        let mut cols = [[0.0; 4]; 4];
+        for (i, col) in cols.iter_mut().enumerate() {
+            for (j, value) in col.iter_mut().enumerate() {
+                *value = c[j][i];
+            }
+        }
+        Self {cols}
+    }
+
+    pub fn transform_point(&self, point: Vec3) -> Vec3 {
+        let p = *self * point.extend(1.0);
+        match p.w != 0.0 && p.w != 1.0 {
+            true => p.truncate() / p.w,
+            false => p.truncate()
+        }
+    }
+
+    pub fn transform_vector(&self, vector: Vec3) -> Vec3 {
+        (*self * vector.extend(0.0)).truncate()
+    }
+
+    pub fn determinant(&self) -> f32 {
+        let m = self.transpose().cols;
+        let mut det = 0.0;
+        for (col, sign) in [(0, 1.0), (1, -1.0), (2, 1.0), (3, -1.0)] {
+            det += sign * m[0][col] * Self::minor(&m, 0, col);
+        }
+        det
+    }
+
+    pub fn inverse(&self) -> Option<Self> {
+        let det = self.determinant();
+        if det.abs() <= f32::EPSILON {
+            return None;
+        }
+
+        // Adjugate is the transposed cofactor matrix, so cofactor (i, j) of the
+        // row-major input lands in cols[i][j] of the column-major output
+        let m = self.transpose().cols;
+        let mut cols = [[0.0; 4]; 4];
+        for (i, col) in cols.iter_mut().enumerate() {
+            for (j, value) in col.iter_mut().enumerate() {
+                let sign = if (i + j) % 2 == 0 { 1.0 } else { -1.0 };
+                *value = sign * Self::minor(&m, i, j) / det;
+            }
+        }
+        Some(Self {cols})
+    }
+
+    fn minor(m: &[[f32; 4]; 4], row: usize, col: usize) -> f32 {
+        let mut sub = [[0.0; 3]; 3];
+        let mut si = 0;
+        for (i, r) in m.iter().enumerate() {
+            if i == row {
+                continue;
+            }
+            let mut sj = 0;
+            for (j, value) in r.iter().enumerate() {
+                if j == col {
+                    continue;
+                }
+                sub[si][sj] = *value;
+                sj += 1;
+            }
+            si += 1;
+        }
+        Mat3 {cols: sub}.determinant()
+    }
+}
+
+impl Mul for Mat4 {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        Self::from_cols(self * rhs.col(0), self * rhs.col(1), self * rhs.col(2), self * rhs.col(3))
+    }
+}
+
+impl Mul<Vec4> for Mat4 {
+    type Output = Vec4;
+
+    fn mul(self, rhs: Vec4) -> Self::Output {
+        self.col(0) * rhs.x + self.col(1) * rhs.y + self.col(2) * rhs.z + self.col(3) * rhs.w
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Quat {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub w: f32
+}
+
+impl Default for Quat {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Quat {
+    pub const IDENTITY: Self = Self {x: 0.0, y: 0.0, z: 0.0, w: 1.0};
+
+    pub fn new(x: f32, y: f32, z: f32, w: f32) -> Self {
+        Self {x, y, z, w}
+    }
+
+    pub fn from_axis_angle(axis: Vec3, angle: f32) -> Self {
+        let axis = axis.normalize();
+        let (sin, cos) = (angle / 2.0).sin_cos();
+
+        Self {x: axis.x * sin, y: axis.y * sin, z: axis.z * sin, w: cos}
+    }
+
+    // Shortest rotation taking direction `from` to direction `to`
+    pub fn from_rotation_arc(from: Vec3, to: Vec3) -> Self {
+        let (from, to) = (from.normalize(), to.normalize());
+        let dot = from.dot(to);
+
+        // Opposite directions: half turn around any perpendicular axis
+        if dot < -0.9999 {
+            let axis = match from.cross(Vec3::X).length_squared() > 1e-6 {
+                true => from.cross(Vec3::X),
+                false => from.cross(Vec3::Y)
+            };
+            return Self::from_axis_angle(axis, std::f32::consts::PI);
+        }
+
+        let axis = from.cross(to);
+        Self::new(axis.x, axis.y, axis.z, 1.0 + dot).normalize()
+    }
+
+    // Angles in radians, applied as yaw (Y), then pitch (X), then roll (Z)
+    pub fn from_euler(yaw: f32, pitch: f32, roll: f32) -> Self {
+        Self::from_axis_angle(Vec3::Y, yaw) * Self::from_axis_angle(Vec3::X, pitch) * Self::from_axis_angle(Vec3::Z, roll)
+    }
+
+    pub fn vector(&self) -> Vec3 {
+        Vec3::new(self.x, self.y, self.z)
+    }
+
+    pub fn dot(&self, rhs: Self) -> f32 {
+        self.x * rhs.x + self.y * rhs.y + self.z * rhs.z + self.w * rhs.w
+    }
+
+    pub fn length(&self) -> f32 {
+        self.dot(*self).sqrt()
+    }
+
+    pub fn normalize(&self) -> Self {
+        let len = self.length();
+        match len > 0.0 {
+            true => Self::new(self.x / len, self.y / len, self.z / len, self.w / len),
+            false => Self::IDENTITY
+        }
+    }
+
+    pub fn conjugate(&self) -> Self {
+        Self::new(-self.x, -self.y, -self.z, self.w)
+    }
+
+    pub fn inverse(&self) -> Self {
+        let len_sq = self.dot(*self);
+        let c = self.conjugate();
+        Self::new(c.x / len_sq, c.y / len_sq, c.z / len_sq, c.w / len_sq)
+    }
+
+    pub fn slerp(&self, rhs: Self, t: f32) -> Self {
+        let mut rhs = rhs;
+        let mut cos = self.dot(rhs);
+
+        // Take the short way around
+        if cos < 0.0 {
+            rhs = Self::new(-rhs.x, -rhs.y, -rhs.z, -rhs.w);
+            cos = -cos;
+        }
+
+        // Nearly parallel: fall back to normalized lerp
+        if cos > 0.9995 {
+            let lerp = Self::new(
+                self.x + (rhs.x - self.x) * t,
+                self.y + (rhs.y - self.y) * t,
+                self.z + (rhs.z - self.z) * t,
+                self.w + (rhs.w - self.w) * t
+            );
+            return lerp.normalize();
+        }
+
+        let angle = cos.acos();
+        let sin = angle.sin();
+        let a = ((1.0 - t) * angle).sin() / sin;
+        let b = (t * angle).sin() / sin;
+
+        Self::new(
+            self.x * a + rhs.x * b,
+            self.y * a + rhs.y * b,
+            self.z * a + rhs.z * b,
+            self.w * a + rhs.w * b
+        )
+    }
+}
+
+impl Mul for Quat {
+    type Output = Self;
+
+    // Hamilton product: (self * rhs) applies rhs first
+    fn mul(self, rhs: Self) -> Self::Output {
+        let v = rhs.vector() * self.w + self.vector() * rhs.w + self.vector().cross(rhs.vector());
+        let w = self.w * rhs.w - self.vector().dot(rhs.vector());
+
+        Self::new(v.x, v.y, v.z, w)
+    }
+}
+
+impl Mul<Vec3> for Quat {
+    type Output = Vec3;
+
+    fn mul(self, rhs: Vec3) -> Self::Output {
+        let u = self.vector();
+        let t = u.cross(rhs) * 2.0;
+
+        rhs + t * self.w + u.cross(t)
+    }
+}
+
+// Axis-aligned bounding box
+#[derive(Clone, Copy, Default, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self {min, max}
+    }
+
+    // Box around the points, empty input gives a box at the origin
+    pub fn from_points<I: IntoIterator<Item = Vec3>>(points: I) -> Self {
+        let mut points = points.into_iter();
+        let Some(first) = points.next() else {
+            return Self::default();
+        };
+
+        points.fold(Self::new(first, first), |aabb, point| Self::new(aabb.min.min(point), aabb.max.max(point)))
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn size(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    pub fn union(&self, other: Aabb) -> Self {
+        Self::new(self.min.min(other.min), self.max.max(other.max))
+    }
+
+    // Box around the transformed corners
+    pub fn transform(&self, matrix: Mat4) -> Self {
+        Self::from_points(self.get_corners().map(|corner| matrix.transform_point(corner)))
+    }
+
+    // Bit 0 of the index picks max.x, bit 1 max.y, bit 2 max.z
+    pub fn get_corners(&self) -> [Vec3; 8] {
+        [0, 1, 2, 3, 4, 5, 6, 7].map(|i| Vec3::new(
+            match i & 1 { 0 => self.min.x, _ => self.max.x },
+            match i & 2 { 0 => self.min.y, _ => self.max.y },
+            match i & 4 { 0 => self.min.z, _ => self.max.z }
+        ))
+    }
+}
+
+#[derive(Clone, Copy, Default, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BoundingSphere {
+    pub center: Vec3,
+    pub radius: f32
+}
+
+impl BoundingSphere {
+    pub fn new(center: Vec3, radius: f32) -> Self {
+        Self {center, radius}
+    }
+
+    // Centred on the points' box: not the smallest sphere, but cheap and it holds every point
+    pub fn from_points<I: IntoIterator<Item = Vec3>>(points: I) -> Self {
+        let points: Vec<Vec3> = points.into_iter().collect();
+        let center = Aabb::from_points(points.iter().copied()).center();
+        let radius = points.iter().map(|point| (*point - center).length()).fold(0.0, f32::max);
+        Self::new(center, radius)
+    }
+
+    // The radius grows with the largest axis scale, so non-uniform scaling still fits inside
+    pub fn transform(&self, matrix: Mat4) -> Self {
+        let scale = (0..3).map(|axis| matrix.col(axis).truncate().length()).fold(0.0, f32::max);
+        Self::new(matrix.transform_point(self.center), self.radius * scale)
+    }
+}
+
+// Points where normal.dot(point) + distance >= 0 are in front of the plane
+#[derive(Clone, Copy, Default, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FrustumPlane {
+    pub normal: Vec3,
+    pub distance: f32
+}
+
+impl FrustumPlane {
+    pub fn new(normal: Vec3, distance: f32) -> Self {
+        Self {normal, distance}
+    }
+
+    // Unit normal, so distances come out in world units. Planes without a normal stay as they are.
+    pub fn normalize(&self) -> Self {
+        let length = self.normal.length();
+        match length > f32::EPSILON {
+            true => Self::new(self.normal / length, self.distance / length),
+            false => *self
+        }
+    }
+
+    // Signed, negative behind the plane
+    pub fn distance_to(&self, point: Vec3) -> f32 {
+        self.normal.dot(point) + self.distance
+    }
+}
+
+// Planes facing into the view volume: left, right, bottom, top and the two depth planes
+#[derive(Clone, Copy, Default, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Frustum {
+    pub planes: [FrustumPlane; 6]
+}
+
+impl Frustum {
+    // Planes of a projection * view matrix, in the space the matrix maps from (Gribb & Hartmann).
+    // An infinite far plane comes out with a zero normal and a positive distance, so it keeps everything.
+    pub fn from_matrix(matrix: Mat4) -> Self {
+        let rows = matrix.transpose();
+        let (x, y, z, w) = (rows.col(0), rows.col(1), rows.col(2), rows.col(3));
+        let plane = |v: Vec4| FrustumPlane::new(v.truncate(), v.w).normalize();
+
+        Self {planes: [plane(w + x), plane(w - x), plane(w + y), plane(w - y), plane(w + z), plane(w - z)]}
+    }
+
+    pub fn contains_point(&self, point: Vec3) -> bool {
+        self.planes.iter().all(|plane| plane.distance_to(point) >= 0.0)
+    }
+
+    pub fn intersects_sphere(&self, sphere: &BoundingSphere) -> bool {
+        self.planes.iter().all(|plane| plane.distance_to(sphere.center) >= -sphere.radius)
+    }
+
+    // Conservative: a box outside the frustum but near one of its edges can still pass
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            // Corner furthest along the normal, if even that one is behind the whole box is
+            let pick = |normal: f32, min: f32, max: f32| match normal >= 0.0 {
+                true => max,
+                false => min
+            };
+            let corner = Vec3::new(
+                pick(plane.normal.x, aabb.min.x, aabb.max.x),
+                pick(plane.normal.y, aabb.min.y, aabb.max.y),
+                pick(plane.normal.z, aabb.min.z, aabb.max.z)
+            );
+            plane.distance_to(corner) >= 0.0
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-5;
+
+    fn assert_vec3(actual: Vec3, expected: Vec3) {
+        assert!(actual.approx_eq(expected, EPSILON), "{:?} != {:?}", actual, expected);
+    }
+
+    fn assert_mat4(actual: Mat4, expected: Mat4) {
+        for (a, e) in actual.cols.iter().flatten().zip(expected.cols.iter().flatten()) {
+            assert!((a - e).abs() <= EPSILON, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn vector_products() {
+        assert_vec3(Vec3::X.cross(Vec3::Y), Vec3::Z);
+        assert_eq!(Vec3::new(1.0, 2.0, 3.0).dot(Vec3::new(4.0, -5.0, 6.0)), 12.0);
+        assert_eq!(Vec3::new(3.0, 4.0, 0.0).length(), 5.0);
+        assert_vec3(Vec3::new(0.0, 0.0, 2.0).normalize(), Vec3::Z);
+        assert_vec3(Vec3::ZERO.lerp(Vec3::new(2.0, 4.0, 6.0), 0.5), Vec3::new(1.0, 2.0, 3.0));
+    }
+
+    #[test]
+    fn look_at_puts_the_target_ahead() {
+        let view = Mat4::look_at(Vec3::new(0.0, 0.0, -5.0), Vec3::ZERO, Vec3::Y);
+        // Left-handed: forward is +Z, right is +X
+        assert_vec3(view.transform_point(Vec3::ZERO), Vec3::new(0.0, 0.0, 5.0));
+        assert_vec3(view.transform_point(Vec3::new(1.0, 2.0, 0.0)), Vec3::new(1.0, 2.0, 5.0));
+
+        let view = Mat4::look_at(Vec3::new(3.0, 0.0, 0.0), Vec3::ZERO, Vec3::Y);
+        assert_vec3(view.transform_point(Vec3::ZERO), Vec3::new(0.0, 0.0, 3.0));
+        // Looking along -X, so +Z is to the right
+        assert_vec3(view.transform_point(Vec3::new(3.0, 0.0, 1.0)), Vec3::new(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn perspective_maps_the_clip_planes() {
+        let projection = Mat4::perspective(radians(90.0), 2.0, 0.5, 10.0);
+        assert!((projection.transform_point(Vec3::new(0.0, 0.0, 0.5)).z + 1.0).abs() <= EPSILON);
+        assert!((projection.transform_point(Vec3::new(0.0, 0.0, 10.0)).z - 1.0).abs() <= EPSILON);
+        // 90 degrees: the edges of the view are at |y| = z, and x is squeezed by the aspect ratio
+        let edge = projection.transform_point(Vec3::new(4.0, 2.0, 2.0));
+        assert!((edge.x - 1.0).abs() <= EPSILON && (edge.y - 1.0).abs() <= EPSILON);
+    }
+
+    fn assert_plane(actual: FrustumPlane, normal: Vec3, distance: f32) {
+        assert_vec3(actual.normal, normal);
+        assert!((actual.distance - distance).abs() <= EPSILON, "{:?} != {:?} {}", actual, normal, distance);
+    }
+
+    #[test]
+    fn frustum_planes_face_inwards() {
+        let frustum = Frustum::from_matrix(Mat4::perspective(radians(90.0), 1.0, 1.0, 10.0));
+        let diagonal = 0.5f32.sqrt();
+        assert_plane(frustum.planes[0], Vec3::new(diagonal, 0.0, diagonal), 0.0);
+        assert_plane(frustum.planes[1], Vec3::new(-diagonal, 0.0, diagonal), 0.0);
+        assert_plane(frustum.planes[2], Vec3::new(0.0, diagonal, diagonal), 0.0);
+        assert_plane(frustum.planes[3], Vec3::new(0.0, -diagonal, diagonal), 0.0);
+        assert_plane(frustum.planes[4], Vec3::Z, -1.0);
+        assert_plane(frustum.planes[5], -Vec3::Z, 10.0);
+
+        // Planes come out in world space when the view is part of the matrix
+        let view = Mat4::look_at(Vec3::new(0.0, 0.0, -5.0), Vec3::ZERO, Vec3::Y);
+        let frustum = Frustum::from_matrix(Mat4::perspective(radians(90.0), 1.0, 1.0, 10.0) * view);
+        assert!(frustum.contains_point(Vec3::ZERO));
+        assert!(frustum.contains_point(Vec3::new(4.0, -4.0, 4.5)));
+        assert!(!frustum.contains_point(Vec3::new(0.0, 0.0, 5.5)));
+        assert!(!frustum.contains_point(Vec3::new(0.0, 0.0, -4.5)));
+
+        // Nothing is too far for an infinite far plane
+        let frustum = Frustum::from_matrix(Mat4::perspective_infinite(radians(90.0), 1.0, 1.0));
+        assert_eq!(frustum.planes[5].normal, Vec3::ZERO);
+        assert!(frustum.contains_point(Vec3::new(0.0, 0.0, 1e6)));
+    }
+
+    #[test]
+    fn frustum_culls_spheres() {
+        let frustum = Frustum::from_matrix(Mat4::perspective(radians(90.0), 1.0, 1.0, 10.0));
+        assert!(frustum.intersects_sphere(&BoundingSphere::new(Vec3::new(0.0, 0.0, 5.0), 1.0)));
+        // Centre outside, but the sphere reaches in
+        assert!(frustum.intersects_sphere(&BoundingSphere::new(Vec3::new(0.0, 0.0, 10.5), 1.0)));
+        assert!(frustum.intersects_sphere(&BoundingSphere::new(Vec3::new(-6.0, 0.0, 5.0), 1.0)));
+
+        assert!(!frustum.intersects_sphere(&BoundingSphere::new(Vec3::new(0.0, 0.0, 11.5), 1.0)));
+        assert!(!frustum.intersects_sphere(&BoundingSphere::new(Vec3::new(-7.0, 0.0, 5.0), 1.0)));
+        assert!(!frustum.intersects_sphere(&BoundingSphere::new(Vec3::new(0.0, 0.0, -2.0), 1.0)));
+    }
+
+    #[test]
+    fn frustum_culls_boxes() {
+        let frustum = Frustum::from_matrix(Mat4::perspective(radians(90.0), 1.0, 1.0, 10.0));
+        assert!(frustum.intersects_aabb(&Aabb::new(Vec3::new(-1.0, -1.0, 4.0), Vec3::new(1.0, 1.0, 6.0))));
+        // Straddling the near plane, and big enough to hold the whole frustum
+        assert!(frustum.intersects_aabb(&Aabb::new(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 2.0))));
+        assert!(frustum.intersects_aabb(&Aabb::new(Vec3::splat(-100.0), Vec3::splat(100.0))));
+
+        assert!(!frustum.intersects_aabb(&Aabb::new(Vec3::new(-1.0, -1.0, 11.0), Vec3::new(1.0, 1.0, 12.0))));
+        assert!(!frustum.intersects_aabb(&Aabb::new(Vec3::new(-9.0, -1.0, 4.0), Vec3::new(-7.0, 1.0, 6.0))));
+        assert!(!frustum.intersects_aabb(&Aabb::new(Vec3::new(-1.0, -1.0, -3.0), Vec3::new(1.0, 1.0, 0.5))));
+
+        // Conservative: outside past the left-far corner, yet in front of every single plane
+        assert!(frustum.intersects_aabb(&Aabb::new(Vec3::new(-12.0, -1.0, 9.8), Vec3::new(-10.2, 1.0, 12.0))));
+    }
+
+    #[test]
+    fn inverse_undoes_the_matrix() {
+        let matrix = Mat4::from_trs(Vec3::new(1.0, -2.0, 3.0), Quat::from_euler(0.3, -0.7, 1.1), Vec3::new(2.0, 0.5, 1.5));
+        let inverse = matrix.inverse().unwrap();
+        assert_mat4(matrix * inverse, Mat4::IDENTITY);
+        assert_mat4(inverse * matrix, Mat4::IDENTITY);
+        assert_vec3(inverse.transform_point(matrix.transform_point(Vec3::new(4.0, 5.0, 6.0))), Vec3::new(4.0, 5.0, 6.0));
+
+        assert!(Mat4::scale(Vec3::new(1.0, 0.0, 1.0)).inverse().is_none());
+    }
+
+    #[test]
+    fn quaternion_rotation() {
+        let quarter = Quat::from_axis_angle(Vec3::Y, radians(90.0));
+        assert_vec3(quarter * Vec3::X, -Vec3::Z);
+        assert_vec3(quarter * Vec3::Y, Vec3::Y);
+        // The product applies the right-hand side first
+        let half = quarter * quarter;
+        assert_vec3(half * Vec3::X, -Vec3::X);
+        let then_x = Quat::from_axis_angle(Vec3::X, radians(90.0)) * quarter;
+        assert_vec3(then_x * Vec3::X, Vec3::Y);
+
+        assert_vec3(quarter.inverse() * (quarter * Vec3::new(1.0, 2.0, 3.0)), Vec3::new(1.0, 2.0, 3.0));
+        assert_mat4(Mat4::rotation(quarter), Mat4::rotation_y(radians(90.0)));
+        assert_vec3(Mat4::rotation(quarter).transform_vector(Vec3::X), -Vec3::Z);
+    }
+
+    #[test]
+    fn quaternion_interpolation() {
+        let start = Quat::IDENTITY;
+        let end = Quat::from_axis_angle(Vec3::Z, radians(90.0));
+        let halfway = start.slerp(end, 0.5);
+        assert!((halfway.length() - 1.0).abs() <= EPSILON);
+        assert_vec3(halfway * Vec3::X, Vec3::new(1.0, 1.0, 0.0).normalize());
+
+        let arc = Quat::from_rotation_arc(Vec3::Z, Vec3::new(1.0, 0.0, 1.0));
+        assert_vec3(arc * Vec3::Z, Vec3::new(1.0, 0.0, 1.0).normalize());
+        assert_vec3(Quat::from_rotation_arc(Vec3::Z, -Vec3::Z) * Vec3::Z, -Vec3::Z);
+    }
+}
@@ -0,0 +1,326 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use glium::backend::{Context, Facade};
+use glium::{IndexBuffer, VertexBuffer};
+use glium::index::PrimitiveType;
+
+use super::math::{Aabb, BoundingSphere, Vec3};
+use super::{teapot, Object, Vertex};
+
+// CPU-side triangle list, attributes are indexed together
+#[derive(Clone, Default, Debug, PartialEq)]
+pub struct Mesh {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub uvs: Vec<[f32; 2]>,
+    pub indices: Vec<u32>
+}
+
+// The teapot model is about 100 units across
+const TEAPOT_SCALE: f32 = 0.01;
+
+impl Mesh {
+    pub fn new(positions: Vec<[f32; 3]>, normals: Vec<[f32; 3]>, uvs: Vec<[f32; 2]>, indices: Vec<u32>) -> Self {
+        Self {positions, normals, uvs, indices}
+    }
+
+    pub fn teapot() -> Self {
+        // Skip the dummy first vertex, the indices are 1-based
+        let positions = teapot::VERTICES[1..].iter().map(|v| {
+            let (x, y, z) = v.position;
+            [x * TEAPOT_SCALE, y * TEAPOT_SCALE, z * TEAPOT_SCALE]
+        }).collect();
+        let normals = teapot::NORMALS[1..].iter().map(|n| [n.normal.0, n.normal.1, n.normal.2]).collect();
+        let indices = teapot::INDICES.iter().map(|i| *i as u32 - 1).collect();
+
+        Self {positions, normals, uvs: Vec::new(), indices}
+    }
+
+    // Box centred on the origin, 4 vertices per face so the edges stay sharp.
+    // Triangles are clockwise seen from outside, same as the teapot.
+    pub fn cuboid(size: Vec3) -> Self {
+        let half = size * 0.5;
+        // Face normal and two axes with u x v = normal
+        let faces = [
+            (Vec3::X, Vec3::Y, Vec3::Z),
+            (-Vec3::X, Vec3::Z, Vec3::Y),
+            (Vec3::Y, Vec3::Z, Vec3::X),
+            (-Vec3::Y, Vec3::X, Vec3::Z),
+            (Vec3::Z, Vec3::X, Vec3::Y),
+            (-Vec3::Z, Vec3::Y, Vec3::X)
+        ];
+
+        let mut mesh = Self::default();
+        for (normal, u, v) in faces {
+            let first = mesh.positions.len() as u32;
+            for (su, sv) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)] {
+                mesh.positions.push(((normal + u * su + v * sv) * half).get_matrix());
+                mesh.normals.push(normal.get_matrix());
+                mesh.uvs.push([(su + 1.0) * 0.5, (sv + 1.0) * 0.5]);
+            }
+            mesh.indices.extend([0, 2, 1, 0, 3, 2].map(|i| first + i));
+        }
+        mesh
+    }
+
+    // Adds the other mesh's triangles to this one
+    pub fn append(&mut self, other: &Mesh) {
+        let offset = self.positions.len() as u32;
+
+        // Keep attribute arrays in step when only one side has them
+        self.normals.resize(self.positions.len(), [0.0, 0.0, 0.0]);
+        self.uvs.resize(self.positions.len(), [0.0, 0.0]);
+
+        self.positions.extend_from_slice(&other.positions);
+        self.normals.extend((0..other.positions.len()).map(|i| other.normals.get(i).copied().unwrap_or([0.0, 0.0, 0.0])));
+        self.uvs.extend((0..other.positions.len()).map(|i| other.uvs.get(i).copied().unwrap_or([0.0, 0.0])));
+        self.indices.extend(other.indices.iter().map(|i| i + offset));
+    }
+
+    // Smooth area-weighted normals; vertices at the same position share one normal.
+    // Front faces are clockwise, so the normal is (c - a) x (b - a).
+    pub fn compute_normals(&mut self) {
+        let mut sums: HashMap<[u32; 3], Vec3> = HashMap::new();
+        let key = |p: [f32; 3]| [p[0].to_bits(), p[1].to_bits(), p[2].to_bits()];
+
+        for triangle in self.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| Vec3::from_matrix(self.positions[triangle[i] as usize]));
+            // Cross product length is twice the triangle area
+            let normal = (c - a).cross(b - a);
+
+            for i in triangle {
+                *sums.entry(key(self.positions[*i as usize])).or_default() += normal;
+            }
+        }
+
+        self.normals = self.positions.iter().map(|p| {
+            sums.get(&key(*p)).copied().unwrap_or_default().normalize().get_matrix()
+        }).collect();
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    pub fn get_bounds(&self) -> Aabb {
+        Aabb::from_points(self.positions.iter().map(|p| Vec3::from_matrix(*p)))
+    }
+
+    pub fn get_bounding_sphere(&self) -> BoundingSphere {
+        BoundingSphere::from_points(self.positions.iter().map(|p| Vec3::from_matrix(*p)))
+    }
+
+    // Interleaved vertices in the layout the shaders expect
+    pub fn get_vertices(&self) -> Vec<Vertex> {
+        self.positions.iter().enumerate().map(|(i, p)| {
+            let n = self.normals.get(i).copied().unwrap_or([0.0, 0.0, 0.0]);
+            let uv = self.uvs.get(i).copied().unwrap_or([0.0, 0.0]);
+            Vertex {position: (p[0], p[1], p[2]), normal: (n[0], n[1], n[2]), tex_coords: (uv[0], uv[1])}
+        }).collect()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct MeshHandle(usize);
+
+// CPU meshes of a world, shared by every object with the same mesh key
+#[derive(Default)]
+pub struct MeshLibrary {
+    meshes: Vec<Mesh>,
+    // Bumped on every replacement so caches know to upload again
+    revisions: Vec<u32>,
+    // Kept next to the mesh so nothing has to walk the vertices per frame
+    bounds: Vec<Aabb>,
+    spheres: Vec<BoundingSphere>,
+    handles: HashMap<String, MeshHandle>
+}
+
+impl MeshLibrary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Replaces the mesh if the key is already taken, keeping the handle
+    pub fn insert(&mut self, key: &str, mesh: Mesh) -> MeshHandle {
+        if let Some(handle) = self.handles.get(key) {
+            self.bounds[handle.0] = mesh.get_bounds();
+            self.spheres[handle.0] = mesh.get_bounding_sphere();
+            self.meshes[handle.0] = mesh;
+            self.revisions[handle.0] += 1;
+            return *handle;
+        }
+
+        let handle = MeshHandle(self.meshes.len());
+        self.bounds.push(mesh.get_bounds());
+        self.spheres.push(mesh.get_bounding_sphere());
+        self.meshes.push(mesh);
+        self.revisions.push(0);
+        self.handles.insert(key.to_string(), handle);
+        handle
+    }
+
+    pub fn find(&self, key: &str) -> Option<MeshHandle> {
+        self.handles.get(key).copied()
+    }
+
+    pub fn get(&self, handle: MeshHandle) -> &Mesh {
+        &self.meshes[handle.0]
+    }
+
+    pub fn get_bounds(&self, handle: MeshHandle) -> Aabb {
+        self.bounds[handle.0]
+    }
+
+    pub fn get_bounding_sphere(&self, handle: MeshHandle) -> BoundingSphere {
+        self.spheres[handle.0]
+    }
+
+    pub fn get_revision(&self, handle: MeshHandle) -> u32 {
+        self.revisions[handle.0]
+    }
+
+    pub fn len(&self) -> usize {
+        self.meshes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.meshes.is_empty()
+    }
+
+    // Mesh of the object, built the first time its key is seen
+    pub fn resolve(&mut self, object: &dyn Object) -> Option<MeshHandle> {
+        let key = object.mesh_key()?;
+        if let Some(handle) = self.find(&key) {
+            return Some(handle);
+        }
+
+        // Other parts of the same file come along, keys already in the library are left alone
+        for (part, mesh) in object.build_meshes() {
+            if self.find(&part).is_none() {
+                self.insert(&part, mesh);
+            }
+        }
+        self.find(&key)
+    }
+}
+
+// Turns CPU meshes into whatever the renderer draws from
+pub trait MeshBackend {
+    type Mesh;
+
+    fn upload(&mut self, mesh: &Mesh) -> Self::Mesh;
+}
+
+pub struct GpuMesh {
+    pub vertices: VertexBuffer<Vertex>,
+    pub indices: IndexBuffer<u32>
+}
+
+pub struct GliumBackend {
+    context: Rc<Context>
+}
+
+impl GliumBackend {
+    pub fn new<F: Facade>(facade: &F) -> Self {
+        Self {context: facade.get_context().clone()}
+    }
+}
+
+impl MeshBackend for GliumBackend {
+    type Mesh = GpuMesh;
+
+    fn upload(&mut self, mesh: &Mesh) -> GpuMesh {
+        let vertices = VertexBuffer::new(&self.context, &mesh.get_vertices()).unwrap();
+        let indices = IndexBuffer::new(&self.context, PrimitiveType::TrianglesList, &mesh.indices).unwrap();
+
+        GpuMesh {vertices, indices}
+    }
+}
+
+// Backend without a GL context, only remembers what would have been uploaded
+#[derive(Default)]
+pub struct HeadlessBackend;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HeadlessMesh {
+    pub vertex_count: usize,
+    pub index_count: usize
+}
+
+impl MeshBackend for HeadlessBackend {
+    type Mesh = HeadlessMesh;
+
+    fn upload(&mut self, mesh: &Mesh) -> HeadlessMesh {
+        HeadlessMesh {vertex_count: mesh.vertex_count(), index_count: mesh.indices.len()}
+    }
+}
+
+// Uploaded meshes with the library revision they were made from, indexed by handle
+pub struct MeshCache<B: MeshBackend> {
+    backend: B,
+    meshes: Vec<Option<(u32, B::Mesh)>>,
+    uploads: usize
+}
+
+impl<B: MeshBackend> MeshCache<B> {
+    pub fn new(backend: B) -> Self {
+        Self {backend, meshes: Vec::new(), uploads: 0}
+    }
+
+    // Uploads on first use and after the library mesh was replaced
+    pub fn get(&mut self, handle: MeshHandle, library: &MeshLibrary) -> &B::Mesh {
+        if self.meshes.len() <= handle.0 {
+            self.meshes.resize_with(handle.0 + 1, || None);
+        }
+
+        let revision = library.get_revision(handle);
+        let slot = &mut self.meshes[handle.0];
+        if !matches!(slot, Some((uploaded, _)) if *uploaded == revision) {
+            *slot = Some((revision, self.backend.upload(library.get(handle))));
+            self.uploads += 1;
+        }
+        &slot.as_ref().unwrap().1
+    }
+
+    pub fn clear(&mut self) {
+        self.meshes.clear();
+    }
+
+    pub fn get_uploads(&self) -> usize {
+        self.uploads
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn meshes_upload_once_per_revision() {
+        let mut library = MeshLibrary::new();
+        let cube = library.insert("cube", Mesh::cuboid(Vec3::ONE));
+        let teapot = library.insert("teapot", Mesh::teapot());
+        let mut cache = MeshCache::new(HeadlessBackend);
+
+        assert_eq!(cache.get(cube, &library), &HeadlessMesh {vertex_count: 24, index_count: 36});
+        cache.get(cube, &library);
+        cache.get(teapot, &library);
+        cache.get(cube, &library);
+        assert_eq!(cache.get_uploads(), 2);
+
+        // Replacing the mesh keeps the handle but uploads it again
+        assert_eq!(library.insert("cube", Mesh::cuboid(Vec3::splat(2.0))), cube);
+        cache.get(cube, &library);
+        cache.get(teapot, &library);
+        assert_eq!(cache.get_uploads(), 3);
+
+        cache.clear();
+        cache.get(teapot, &library);
+        assert_eq!(cache.get_uploads(), 4);
+    }
+}
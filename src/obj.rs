@@ -0,0 +1,485 @@
+// Wavefront OBJ + MTL loader
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::mesh::Mesh;
+use super::scene::NodeId;
+use super::{Material, Model, Object, World};
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io(PathBuf, io::Error),
+    // Line number (1-based) and what went wrong
+    Parse(usize, String)
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjError::Io(path, err) => write!(f, "can't read {}: {}", path.display(), err),
+            ObjError::Parse(line, message) => write!(f, "line {}: {}", line, message)
+        }
+    }
+}
+
+impl std::error::Error for ObjError {}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ObjMaterial {
+    pub name: String,
+    pub ambient: [f32; 3],
+    pub diffuse: [f32; 3],
+    pub specular: [f32; 3],
+    pub shininess: f32,
+    pub opacity: f32,
+
+    // Texture paths, relative to the MTL file
+    pub diffuse_map: Option<PathBuf>,
+    pub specular_map: Option<PathBuf>,
+    pub normal_map: Option<PathBuf>
+}
+
+impl ObjMaterial {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            ambient: [0.0; 3],
+            diffuse: [0.8; 3],
+            specular: [0.0; 3],
+            shininess: 1.0,
+            opacity: 1.0,
+            diffuse_map: None,
+            specular_map: None,
+            normal_map: None
+        }
+    }
+}
+
+// One `o`/`g` group, split further when the material changes
+#[derive(Clone, Debug, PartialEq)]
+pub struct ObjGroup {
+    pub name: String,
+    pub material: Option<String>,
+    pub mesh: Mesh
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ObjModel {
+    pub groups: Vec<ObjGroup>,
+    pub materials: HashMap<String, ObjMaterial>
+}
+
+impl ObjModel {
+    // All groups in one mesh
+    pub fn merged(&self) -> Mesh {
+        let mut mesh = Mesh::default();
+        for group in &self.groups {
+            mesh.append(&group.mesh);
+        }
+        mesh
+    }
+
+    pub fn get_group(&self, name: &str) -> Option<&ObjGroup> {
+        self.groups.iter().find(|group| group.name == name)
+    }
+}
+
+pub fn load_obj<P: AsRef<Path>>(path: P) -> Result<ObjModel, ObjError> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path).map_err(|err| ObjError::Io(path.to_path_buf(), err))?;
+    let base = path.parent().unwrap_or(Path::new(""));
+
+    // A broken material library only costs the materials, groups using them get the default one
+    parse_obj(&source, |name| {
+        let mtl_path = base.join(name);
+        let materials = fs::read_to_string(&mtl_path)
+            .map_err(|err| ObjError::Io(mtl_path.clone(), err))
+            .and_then(|mtl| parse_mtl(&mtl, mtl_path.parent().unwrap_or(Path::new(""))));
+        match materials {
+            Ok(materials) => Ok(materials),
+            Err(err) => {
+                println!("Can't load material library {}: {}", mtl_path.display(), err);
+                Ok(Vec::new())
+            }
+        }
+    })
+}
+
+// Group being assembled; vertices are deduplicated per (position, uv, normal) triple
+struct GroupBuilder {
+    name: String,
+    material: Option<String>,
+    mesh: Mesh,
+    has_normals: bool,
+    vertices: HashMap<(usize, Option<usize>, Option<usize>), u32>
+}
+
+impl GroupBuilder {
+    fn new(name: &str, material: Option<String>) -> Self {
+        Self {name: name.to_string(), material, mesh: Mesh::default(), has_normals: true, vertices: HashMap::new()}
+    }
+
+    fn finish(mut self) -> Option<ObjGroup> {
+        if self.mesh.indices.is_empty() {
+            return None;
+        }
+
+        if !self.has_normals {
+            self.mesh.compute_normals();
+        }
+        Some(ObjGroup {name: self.name, material: self.material, mesh: self.mesh})
+    }
+}
+
+// `load_mtl` is called with every `mtllib` argument
+pub fn parse_obj<F>(source: &str, mut load_mtl: F) -> Result<ObjModel, ObjError>
+where F: FnMut(&str) -> Result<Vec<ObjMaterial>, ObjError> {
+    let mut positions: Vec<[f32; 3]> = Vec::new();
+    let mut uvs: Vec<[f32; 2]> = Vec::new();
+    let mut normals: Vec<[f32; 3]> = Vec::new();
+
+    let mut model = ObjModel::default();
+    let mut group = GroupBuilder::new("default", None);
+    // Name from the last `o`/`g`, material splits get it as a prefix
+    let mut group_name = "default".to_string();
+
+    for (number, line) in source.lines().enumerate() {
+        let number = number + 1;
+        let line = line.split('#').next().unwrap_or("").trim();
+
+        let mut parts = line.split_whitespace();
+        let Some(keyword) = parts.next() else {
+            continue;
+        };
+        let args: Vec<&str> = parts.collect();
+
+        match keyword {
+            "v" => positions.push(parse_floats::<3>(&args, number)?),
+            "vt" => {
+                // The optional w coordinate is ignored
+                let uv = parse_floats::<2>(&args, number)?;
+                uvs.push(uv);
+            },
+            "vn" => normals.push(parse_floats::<3>(&args, number)?),
+            "f" => {
+                if args.len() < 3 {
+                    return Err(ObjError::Parse(number, "face needs at least 3 vertices".to_string()));
+                }
+
+                let mut face = Vec::with_capacity(args.len());
+                for arg in &args {
+                    let (v, vt, vn) = parse_face_vertex(arg, positions.len(), uvs.len(), normals.len(), number)?;
+                    if vn.is_none() {
+                        group.has_normals = false;
+                    }
+
+                    let next = group.mesh.positions.len() as u32;
+                    let index = *group.vertices.entry((v, vt, vn)).or_insert(next);
+                    if index == next {
+                        group.mesh.positions.push(positions[v]);
+                        group.mesh.uvs.push(vt.map(|i| uvs[i]).unwrap_or([0.0, 0.0]));
+                        group.mesh.normals.push(vn.map(|i| normals[i]).unwrap_or([0.0, 0.0, 0.0]));
+                    }
+                    face.push(index);
+                }
+
+                // Fan triangulation, fine for the convex polygons exporters write. OBJ faces
+                // are counter-clockwise from the front, the engine's are clockwise.
+                for i in 1..face.len() - 1 {
+                    group.mesh.indices.extend_from_slice(&[face[0], face[i + 1], face[i]]);
+                }
+            },
+            "o" | "g" => {
+                group_name = args.first().copied().unwrap_or("default").to_string();
+                let material = group.material.clone();
+                let finished = std::mem::replace(&mut group, GroupBuilder::new(&group_name, material));
+                push_group(&mut model, finished);
+            },
+            "usemtl" => {
+                let material = args.first().map(|name| name.to_string());
+                if group.mesh.indices.is_empty() {
+                    group.material = material;
+                } else {
+                    let name = format!("{}_{}", group_name, material.as_deref().unwrap_or(""));
+                    let finished = std::mem::replace(&mut group, GroupBuilder::new(&name, material));
+                    push_group(&mut model, finished);
+                }
+            },
+            "mtllib" => {
+                for name in &args {
+                    for material in load_mtl(name)? {
+                        model.materials.insert(material.name.clone(), material);
+                    }
+                }
+            },
+            // Smoothing groups, lines, points and the rest are not needed for rendering
+            _ => ()
+        }
+    }
+
+    push_group(&mut model, group);
+    Ok(model)
+}
+
+// Group names are made unique so they can be used as mesh keys
+fn push_group(model: &mut ObjModel, group: GroupBuilder) {
+    let Some(mut group) = group.finish() else {
+        return;
+    };
+
+    let base = group.name.clone();
+    let mut n = 2;
+    while model.get_group(&group.name).is_some() {
+        group.name = format!("{}.{}", base, n);
+        n += 1;
+    }
+    model.groups.push(group);
+}
+
+pub fn parse_mtl(source: &str, base: &Path) -> Result<Vec<ObjMaterial>, ObjError> {
+    let mut materials: Vec<ObjMaterial> = Vec::new();
+
+    for (number, line) in source.lines().enumerate() {
+        let number = number + 1;
+        let line = line.split('#').next().unwrap_or("").trim();
+
+        let mut parts = line.split_whitespace();
+        let Some(keyword) = parts.next() else {
+            continue;
+        };
+        let args: Vec<&str> = parts.collect();
+
+        if keyword == "newmtl" {
+            let name = args.first().ok_or_else(|| ObjError::Parse(number, "newmtl without a name".to_string()))?;
+            materials.push(ObjMaterial::new(name));
+            continue;
+        }
+
+        let Some(material) = materials.last_mut() else {
+            return Err(ObjError::Parse(number, format!("`{}` before newmtl", keyword)));
+        };
+
+        // Map options like `-bm 1.0` come before the file name, which is always last
+        let texture = || args.last().map(|name| base.join(name));
+
+        match keyword {
+            "Ka" => material.ambient = parse_floats::<3>(&args, number)?,
+            "Kd" => material.diffuse = parse_floats::<3>(&args, number)?,
+            "Ks" => material.specular = parse_floats::<3>(&args, number)?,
+            "Ns" => material.shininess = parse_floats::<1>(&args, number)?[0],
+            "d" => material.opacity = parse_floats::<1>(&args, number)?[0],
+            "Tr" => material.opacity = 1.0 - parse_floats::<1>(&args, number)?[0],
+            "map_Kd" => material.diffuse_map = texture(),
+            "map_Ks" => material.specular_map = texture(),
+            "map_Bump" | "map_bump" | "bump" | "norm" => material.normal_map = texture(),
+            _ => ()
+        }
+    }
+
+    Ok(materials)
+}
+
+fn parse_floats<const N: usize>(args: &[&str], line: usize) -> Result<[f32; N], ObjError> {
+    if args.len() < N {
+        return Err(ObjError::Parse(line, format!("expected {} numbers, found {}", N, args.len())));
+    }
+
+    let mut values = [0.0; N];
+    for (value, arg) in values.iter_mut().zip(args) {
+        *value = arg.parse().map_err(|_| ObjError::Parse(line, format!("`{}` is not a number", arg)))?;
+    }
+    Ok(values)
+}
+
+// OBJ indices are 1-based, negative ones count back from the last element read so far
+fn resolve_index(value: &str, count: usize, line: usize) -> Result<usize, ObjError> {
+    let index: i64 = value.parse().map_err(|_| ObjError::Parse(line, format!("`{}` is not an index", value)))?;
+
+    let resolved = match index {
+        i if i > 0 => i - 1,
+        i if i < 0 => count as i64 + i,
+        _ => return Err(ObjError::Parse(line, "index 0 is not valid".to_string()))
+    };
+
+    match resolved >= 0 && (resolved as usize) < count {
+        true => Ok(resolved as usize),
+        false => Err(ObjError::Parse(line, format!("index {} out of range", index)))
+    }
+}
+
+// `v`, `v/vt`, `v//vn` or `v/vt/vn`
+fn parse_face_vertex(arg: &str, positions: usize, uvs: usize, normals: usize, line: usize) -> Result<(usize, Option<usize>, Option<usize>), ObjError> {
+    let mut parts = arg.split('/');
+
+    let v = resolve_index(parts.next().unwrap_or(""), positions, line)?;
+    let vt = match parts.next() {
+        Some(value) if !value.is_empty() => Some(resolve_index(value, uvs, line)?),
+        _ => None
+    };
+    let vn = match parts.next() {
+        Some(value) if !value.is_empty() => Some(resolve_index(value, normals, line)?),
+        _ => None
+    };
+
+    Ok((v, vt, vn))
+}
+
+impl World {
+    // Adds an empty root named after the file with one child per group, using the group's MTL material.
+    // Meshes go straight into the library so the file is only parsed once.
+    pub fn load_obj(&mut self, path: &str) -> Result<NodeId, ObjError> {
+        let model = load_obj(path)?;
+
+        let name = Path::new(path).file_stem().and_then(|stem| stem.to_str()).unwrap_or(path);
+        let root = self.add_object(Model::new(name));
+
+        for group in model.groups {
+            let mut child = Model::from_file(&group.name, path, Some(&group.name));
+            if let Some(material) = group.material.as_ref().and_then(|name| model.materials.get(name)) {
+                child.material = Material::from(material);
+            }
+
+            self.get_meshes_mut().insert(&Model::get_mesh_key(path, Some(&group.name)), group.mesh);
+            self.add_child(root, child);
+        }
+
+        Ok(root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::math::Vec3;
+
+    fn fixture(name: &str) -> String {
+        format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+
+    // Normal of every triangle as the engine sees it, front faces being clockwise
+    fn face_normals(mesh: &Mesh) -> Vec<Vec3> {
+        mesh.indices.chunks_exact(3).map(|triangle| {
+            let [a, b, c] = [0, 1, 2].map(|i| Vec3::from_matrix(mesh.positions[triangle[i] as usize]));
+            (c - a).cross(b - a).normalize()
+        }).collect()
+    }
+
+    #[test]
+    fn quads_face_the_way_their_normals_point() {
+        let model = load_obj(fixture("quad.obj")).unwrap();
+        assert_eq!(model.groups.len(), 1);
+
+        let group = model.get_group("Quad").unwrap();
+        assert_eq!(group.mesh.vertex_count(), 4);
+        assert_eq!(group.mesh.triangle_count(), 2);
+        assert_eq!(group.mesh.uvs[2], [1.0, 1.0]);
+        for normal in face_normals(&group.mesh) {
+            assert!(normal.approx_eq(-Vec3::Z, 1e-6));
+        }
+
+        assert_eq!(group.material.as_deref(), Some("Red"));
+        let red = &model.materials["Red"];
+        assert_eq!((red.diffuse, red.shininess), ([1.0, 0.0, 0.0], 32.0));
+    }
+
+    #[test]
+    fn negative_indices_count_back() {
+        let model = load_obj(fixture("negative.obj")).unwrap();
+        let mesh = &model.groups[0].mesh;
+        assert_eq!(mesh.vertex_count(), 4);
+        assert_eq!(mesh.triangle_count(), 2);
+
+        // -4 -2 -1 after the fourth vertex is 1 3 4, reversed to 1 4 3
+        let second: Vec<[f32; 3]> = mesh.indices[3..].iter().map(|i| mesh.positions[*i as usize]).collect();
+        assert_eq!(second, vec![[0.0, 0.0, 0.0], [0.0, 1.0, 0.0], [1.0, 1.0, 0.0]]);
+        for normal in face_normals(mesh) {
+            assert!(normal.approx_eq(Vec3::Z, 1e-6));
+        }
+        assert!(mesh.normals.iter().all(|normal| *normal == [0.0, 0.0, 1.0]));
+    }
+
+    #[test]
+    fn polygons_become_fans() {
+        let model = load_obj(fixture("hexagon.obj")).unwrap();
+        let names: Vec<&str> = model.groups.iter().map(|group| group.name.as_str()).collect();
+        assert_eq!(names, ["Hexagon", "Pentagon"]);
+
+        let hexagon = &model.get_group("Hexagon").unwrap().mesh;
+        let pentagon = &model.get_group("Pentagon").unwrap().mesh;
+        assert_eq!((hexagon.vertex_count(), hexagon.triangle_count()), (6, 4));
+        assert_eq!((pentagon.vertex_count(), pentagon.triangle_count()), (5, 3));
+        assert_eq!(model.merged().triangle_count(), 7);
+
+        for mesh in [hexagon, pentagon] {
+            for normal in face_normals(mesh) {
+                assert!(normal.approx_eq(Vec3::Z, 1e-5));
+            }
+            // Computed from the winding since the file has none
+            for normal in &mesh.normals {
+                assert!(Vec3::from_matrix(*normal).approx_eq(Vec3::Z, 1e-5));
+            }
+        }
+    }
+
+    #[test]
+    fn missing_normals_point_outwards() {
+        let model = load_obj(fixture("no_normals.obj")).unwrap();
+        let mesh = &model.groups[0].mesh;
+        assert_eq!(model.groups[0].name, "default");
+        assert_eq!((mesh.vertex_count(), mesh.triangle_count()), (8, 12));
+
+        // Every corner gets one smooth normal pointing away from the centre
+        for (position, normal) in mesh.positions.iter().zip(&mesh.normals) {
+            let normal = Vec3::from_matrix(*normal);
+            assert!((normal.length() - 1.0).abs() < 1e-5);
+            assert!(normal.dot(Vec3::from_matrix(*position).normalize()) > 0.5, "{:?} at {:?}", normal, position);
+        }
+        for (triangle, normal) in mesh.indices.chunks_exact(3).zip(face_normals(mesh)) {
+            let centre = triangle.iter().map(|i| Vec3::from_matrix(mesh.positions[*i as usize])).fold(Vec3::ZERO, |sum, p| sum + p);
+            assert!(normal.dot(centre) > 0.0);
+        }
+    }
+
+    #[test]
+    fn bad_indices_are_errors() {
+        let no_mtl = |_: &str| Ok(Vec::new());
+        let zero = parse_obj("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 0 1 2", no_mtl);
+        assert!(matches!(zero, Err(ObjError::Parse(4, _))));
+        let out_of_range = parse_obj("v 0 0 0\nv 1 0 0\nf 1 2 -3", no_mtl);
+        assert!(matches!(out_of_range, Err(ObjError::Parse(3, _))));
+        let missing = load_obj(fixture("missing.obj"));
+        assert!(matches!(missing, Err(ObjError::Io(..))));
+    }
+
+    #[test]
+    fn missing_material_libraries_fall_back_to_the_default() {
+        let path = fixture("dangling_mtl.obj");
+        let model = load_obj(&path).unwrap();
+        assert!(model.materials.is_empty());
+        assert_eq!(model.get_group("Quad").unwrap().material.as_deref(), Some("Red"));
+
+        let mut world = World::new("Obj");
+        let root = world.load_obj(&path).unwrap();
+        let quad = world.get_as::<Model>(world.get_children(root)[0]).unwrap();
+        assert_eq!(quad.material, Material::default());
+    }
+
+    #[test]
+    fn models_fill_the_library_from_one_read() {
+        let path = fixture("hexagon.obj");
+        let mut world = World::new("Obj");
+        let hexagon = world.add_object(Model::from_file("Hexagon", &path, Some("Hexagon")));
+        world.update_transforms();
+
+        assert!(world.get_node(hexagon).unwrap().get_mesh().is_some());
+        assert_eq!(world.get_meshes().len(), 2);
+        assert!(world.get_meshes().find(&Model::get_mesh_key(&path, Some("Pentagon"))).is_some());
+
+        let root = world.load_obj(&path).unwrap();
+        assert_eq!(world.get_children(root).len(), 2);
+        assert_eq!(world.get_meshes().len(), 2);
+    }
+}
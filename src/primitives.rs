@@ -0,0 +1,484 @@
+// Procedural meshes and the objects that use them
+use std::collections::HashMap;
+use std::f32::consts::{PI, TAU};
+
+use serde::{Serialize, Deserialize};
+
+use super::math::{Vec2, Vec3};
+use super::mesh::Mesh;
+use super::{Material, Object, Transform};
+
+// Every generator winds triangles clockwise seen from outside, like the teapot
+impl Mesh {
+    // (columns + 1) x (rows + 1) vertices, `f` gives position, normal and uv of each.
+    // The surface must be parametrized so that d/dcolumn x d/drow points inward.
+    fn add_grid<F: Fn(u32, u32) -> (Vec3, Vec3, [f32; 2])>(&mut self, columns: u32, rows: u32, f: F) {
+        let first = self.positions.len() as u32;
+
+        for row in 0..=rows {
+            for column in 0..=columns {
+                let (position, normal, uv) = f(column, row);
+                self.positions.push(position.get_matrix());
+                self.normals.push(normal.get_matrix());
+                self.uvs.push(uv);
+            }
+        }
+
+        let stride = columns + 1;
+        for row in 0..rows {
+            for column in 0..columns {
+                let a = first + row * stride + column;
+                let b = a + 1;
+                let c = b + stride;
+                let d = a + stride;
+                self.indices.extend_from_slice(&[a, b, c, a, c, d]);
+            }
+        }
+    }
+
+    // Flat disc at height `y` facing up or down
+    fn add_disc(&mut self, segments: u32, radius: f32, y: f32, up: bool) {
+        let first = self.positions.len() as u32;
+        let normal = match up {
+            true => Vec3::Y,
+            false => -Vec3::Y
+        };
+
+        self.positions.push([0.0, y, 0.0]);
+        self.normals.push(normal.get_matrix());
+        self.uvs.push([0.5, 0.5]);
+
+        for segment in 0..=segments {
+            let (sin, cos) = (segment as f32 / segments as f32 * TAU).sin_cos();
+            self.positions.push([radius * cos, y, radius * sin]);
+            self.normals.push(normal.get_matrix());
+            self.uvs.push([0.5 + 0.5 * cos, 0.5 + 0.5 * sin]);
+        }
+
+        for segment in 0..segments {
+            let (a, b) = (first + 1 + segment, first + 2 + segment);
+            match up {
+                true => self.indices.extend_from_slice(&[first, a, b]),
+                false => self.indices.extend_from_slice(&[first, b, a])
+            }
+        }
+    }
+
+    // Latitude/longitude sphere, the seam column is duplicated for the uvs
+    pub fn uv_sphere(radius: f32, segments: u32, rings: u32) -> Self {
+        let (segments, rings) = (segments.max(3), rings.max(2));
+
+        let mut mesh = Self::default();
+        mesh.add_grid(segments, rings, |column, row| {
+            let u = column as f32 / segments as f32;
+            let v = row as f32 / rings as f32;
+            let (sin_theta, cos_theta) = (u * TAU).sin_cos();
+            let (sin_phi, cos_phi) = ((v - 0.5) * PI).sin_cos();
+
+            let normal = Vec3::new(cos_phi * cos_theta, sin_phi, cos_phi * sin_theta);
+            (normal * radius, normal, [u, v])
+        });
+        mesh
+    }
+
+    // Subdivided icosahedron: 10 * 4^n + 2 shared vertices, uvs from the direction
+    pub fn icosphere(radius: f32, subdivisions: u32) -> Self {
+        let t = (1.0 + 5f32.sqrt()) / 2.0;
+        let mut directions: Vec<Vec3> = [
+            (-1.0, t, 0.0), (1.0, t, 0.0), (-1.0, -t, 0.0), (1.0, -t, 0.0),
+            (0.0, -1.0, t), (0.0, 1.0, t), (0.0, -1.0, -t), (0.0, 1.0, -t),
+            (t, 0.0, -1.0), (t, 0.0, 1.0), (-t, 0.0, -1.0), (-t, 0.0, 1.0)
+        ].iter().map(|(x, y, z)| Vec3::new(*x, *y, *z).normalize()).collect();
+
+        let mut triangles: Vec<[u32; 3]> = vec![
+            [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+            [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+            [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+            [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1]
+        ];
+
+        for _ in 0..subdivisions {
+            // Edge midpoints are shared by the two triangles on either side
+            let mut midpoints: HashMap<(u32, u32), u32> = HashMap::new();
+            let mut midpoint = |a: u32, b: u32| *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                directions.push((directions[a as usize] + directions[b as usize]).normalize());
+                directions.len() as u32 - 1
+            });
+
+            triangles = triangles.iter().flat_map(|&[a, b, c]| {
+                let (ab, bc, ca) = (midpoint(a, b), midpoint(b, c), midpoint(c, a));
+                [[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+            }).collect();
+        }
+
+        let mut mesh = Self::default();
+        for direction in &directions {
+            mesh.positions.push((*direction * radius).get_matrix());
+            mesh.normals.push(direction.get_matrix());
+            mesh.uvs.push([
+                0.5 + direction.z.atan2(direction.x) / TAU,
+                0.5 + direction.y.asin() / PI
+            ]);
+        }
+
+        for [a, b, c] in triangles {
+            let [pa, pb, pc] = [a, b, c].map(|i| directions[i as usize]);
+            match (pb - pa).cross(pc - pa).dot(pa) > 0.0 {
+                true => mesh.indices.extend_from_slice(&[a, c, b]),
+                false => mesh.indices.extend_from_slice(&[a, b, c])
+            }
+        }
+        mesh
+    }
+
+    // Flat plane on XZ facing +Y
+    pub fn plane(size: Vec2, subdivisions: u32) -> Self {
+        let cells = subdivisions.max(1);
+
+        let mut mesh = Self::default();
+        mesh.add_grid(cells, cells, |column, row| {
+            let u = column as f32 / cells as f32;
+            let v = row as f32 / cells as f32;
+            (Vec3::new(size.x * (u - 0.5), 0.0, size.y * (v - 0.5)), Vec3::Y, [u, v])
+        });
+        mesh
+    }
+
+    // Capped cylinder along Y, centred on the origin
+    pub fn cylinder(radius: f32, height: f32, segments: u32) -> Self {
+        let segments = segments.max(3);
+
+        let mut mesh = Self::default();
+        mesh.add_grid(segments, 1, |column, row| {
+            let u = column as f32 / segments as f32;
+            let (sin, cos) = (u * TAU).sin_cos();
+
+            let normal = Vec3::new(cos, 0.0, sin);
+            let y = height * (row as f32 - 0.5);
+            (Vec3::new(radius * cos, y, radius * sin), normal, [u, row as f32])
+        });
+        mesh.add_disc(segments, radius, height * 0.5, true);
+        mesh.add_disc(segments, radius, -height * 0.5, false);
+        mesh
+    }
+
+    // Cone along Y with the base at -height / 2 and the tip at +height / 2
+    pub fn cone(radius: f32, height: f32, segments: u32) -> Self {
+        let segments = segments.max(3);
+        // Side normals lean up by the slope angle
+        let slope = Vec2::new(height, radius).normalize();
+
+        let mut mesh = Self::default();
+        mesh.add_grid(segments, 1, |column, row| {
+            let u = column as f32 / segments as f32;
+            let (sin, cos) = (u * TAU).sin_cos();
+
+            let normal = Vec3::new(slope.x * cos, slope.y, slope.x * sin);
+            let ring = radius * (1.0 - row as f32);
+            let y = height * (row as f32 - 0.5);
+            (Vec3::new(ring * cos, y, ring * sin), normal, [u, row as f32])
+        });
+        mesh.add_disc(segments, radius, -height * 0.5, false);
+        mesh
+    }
+
+    // Ring around Y, `radius` to the middle of the tube
+    pub fn torus(radius: f32, tube_radius: f32, segments: u32, sides: u32) -> Self {
+        let (segments, sides) = (segments.max(3), sides.max(3));
+
+        let mut mesh = Self::default();
+        mesh.add_grid(segments, sides, |column, row| {
+            let u = column as f32 / segments as f32;
+            let v = row as f32 / sides as f32;
+            let (sin_theta, cos_theta) = (u * TAU).sin_cos();
+            let (sin_phi, cos_phi) = (v * TAU).sin_cos();
+
+            let normal = Vec3::new(cos_phi * cos_theta, sin_phi, cos_phi * sin_theta);
+            let centre = Vec3::new(radius * cos_theta, 0.0, radius * sin_theta);
+            (centre + normal * tube_radius, normal, [u, v])
+        });
+        mesh
+    }
+
+    // Cylinder with hemispherical ends, `height` includes the ends
+    pub fn capsule(radius: f32, height: f32, segments: u32, rings: u32) -> Self {
+        let (segments, rings) = (segments.max(3), rings.max(1));
+        let half = (height * 0.5 - radius).max(0.0);
+        let total = 2.0 * (half + radius);
+
+        // Rows 0..=rings are the bottom hemisphere, the rest the top one;
+        // the band between the two equators is the cylinder
+        let mut mesh = Self::default();
+        mesh.add_grid(segments, 2 * rings + 1, |column, row| {
+            let u = column as f32 / segments as f32;
+            let (sin_theta, cos_theta) = (u * TAU).sin_cos();
+            let (phi, offset) = match row <= rings {
+                true => ((row as f32 / rings as f32 - 1.0) * PI / 2.0, -half),
+                false => ((row - rings - 1) as f32 / rings as f32 * PI / 2.0, half)
+            };
+            let (sin_phi, cos_phi) = phi.sin_cos();
+
+            let normal = Vec3::new(cos_phi * cos_theta, sin_phi, cos_phi * sin_theta);
+            let position = normal * radius + Vec3::new(0.0, offset, 0.0);
+            (position, normal, [u, match total > 0.0 {
+                true => position.y / total + 0.5,
+                false => 0.5
+            }])
+        });
+        mesh
+    }
+}
+
+// Fields every primitive shares; the rest is tessellation, baked into the mesh key
+macro_rules! impl_primitive {
+    ($name:ident, $object:ident => $key:expr, $mesh:expr) => {
+        #[typetag::serde]
+        impl Object for $name {
+            fn new(name: &str) -> Self where Self: Sized {
+                let name = name.to_string();
+                Self {name, ..Default::default()}
+            }
+
+            fn get_name(&self) -> &str {
+                &self.name
+            }
+
+            fn transform(&self) -> &Transform {
+                &self.transform
+            }
+
+            fn transform_mut(&mut self) -> &mut Transform {
+                &mut self.transform
+            }
+
+            fn mesh_key(&self) -> Option<String> {
+                let $object = self;
+                Some($key)
+            }
+
+            fn build_mesh(&self) -> Option<Mesh> {
+                let $object = self;
+                Some($mesh)
+            }
+
+            fn material(&self) -> Option<&Material> {
+                Some(&self.material)
+            }
+
+            fn material_mut(&mut self) -> Option<&mut Material> {
+                Some(&mut self.material)
+            }
+        }
+    };
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Sphere {
+    name: String,
+
+    pub transform: Transform,
+    pub radius: f32,
+    pub segments: u32,
+    pub rings: u32,
+    #[serde(default)]
+    pub material: Material
+}
+
+impl Default for Sphere {
+    fn default() -> Self {
+        Self {name: String::new(), transform: Transform::IDENTITY, radius: 0.5, segments: 32, rings: 16, material: Material::default()}
+    }
+}
+
+impl_primitive!(Sphere, sphere => format!("sphere:{}:{}x{}", sphere.radius, sphere.segments, sphere.rings),
+    Mesh::uv_sphere(sphere.radius, sphere.segments, sphere.rings));
+
+#[derive(Serialize, Deserialize)]
+pub struct IcoSphere {
+    name: String,
+
+    pub transform: Transform,
+    pub radius: f32,
+    pub subdivisions: u32,
+    #[serde(default)]
+    pub material: Material
+}
+
+impl Default for IcoSphere {
+    fn default() -> Self {
+        Self {name: String::new(), transform: Transform::IDENTITY, radius: 0.5, subdivisions: 2, material: Material::default()}
+    }
+}
+
+impl_primitive!(IcoSphere, sphere => format!("icosphere:{}:{}", sphere.radius, sphere.subdivisions),
+    Mesh::icosphere(sphere.radius, sphere.subdivisions));
+
+#[derive(Serialize, Deserialize)]
+pub struct Plane {
+    name: String,
+
+    pub transform: Transform,
+    pub size: Vec2,
+    pub subdivisions: u32,
+    #[serde(default)]
+    pub material: Material
+}
+
+impl Default for Plane {
+    fn default() -> Self {
+        Self {name: String::new(), transform: Transform::IDENTITY, size: Vec2::new(1.0, 1.0), subdivisions: 1, material: Material::default()}
+    }
+}
+
+impl_primitive!(Plane, plane => format!("plane:{}x{}:{}", plane.size.x, plane.size.y, plane.subdivisions),
+    Mesh::plane(plane.size, plane.subdivisions));
+
+#[derive(Serialize, Deserialize)]
+pub struct Cylinder {
+    name: String,
+
+    pub transform: Transform,
+    pub radius: f32,
+    pub height: f32,
+    pub segments: u32,
+    #[serde(default)]
+    pub material: Material
+}
+
+impl Default for Cylinder {
+    fn default() -> Self {
+        Self {name: String::new(), transform: Transform::IDENTITY, radius: 0.5, height: 1.0, segments: 32, material: Material::default()}
+    }
+}
+
+impl_primitive!(Cylinder, cylinder => format!("cylinder:{}x{}:{}", cylinder.radius, cylinder.height, cylinder.segments),
+    Mesh::cylinder(cylinder.radius, cylinder.height, cylinder.segments));
+
+#[derive(Serialize, Deserialize)]
+pub struct Cone {
+    name: String,
+
+    pub transform: Transform,
+    pub radius: f32,
+    pub height: f32,
+    pub segments: u32,
+    #[serde(default)]
+    pub material: Material
+}
+
+impl Default for Cone {
+    fn default() -> Self {
+        Self {name: String::new(), transform: Transform::IDENTITY, radius: 0.5, height: 1.0, segments: 32, material: Material::default()}
+    }
+}
+
+impl_primitive!(Cone, cone => format!("cone:{}x{}:{}", cone.radius, cone.height, cone.segments),
+    Mesh::cone(cone.radius, cone.height, cone.segments));
+
+#[derive(Serialize, Deserialize)]
+pub struct Torus {
+    name: String,
+
+    pub transform: Transform,
+    pub radius: f32,
+    pub tube_radius: f32,
+    pub segments: u32,
+    pub sides: u32,
+    #[serde(default)]
+    pub material: Material
+}
+
+impl Default for Torus {
+    fn default() -> Self {
+        Self {name: String::new(), transform: Transform::IDENTITY, radius: 0.5, tube_radius: 0.2, segments: 32, sides: 16, material: Material::default()}
+    }
+}
+
+impl_primitive!(Torus, torus => format!("torus:{}x{}:{}x{}", torus.radius, torus.tube_radius, torus.segments, torus.sides),
+    Mesh::torus(torus.radius, torus.tube_radius, torus.segments, torus.sides));
+
+#[derive(Serialize, Deserialize)]
+pub struct Capsule {
+    name: String,
+
+    pub transform: Transform,
+    pub radius: f32,
+    pub height: f32,
+    pub segments: u32,
+    pub rings: u32,
+    #[serde(default)]
+    pub material: Material
+}
+
+impl Default for Capsule {
+    fn default() -> Self {
+        Self {name: String::new(), transform: Transform::IDENTITY, radius: 0.25, height: 1.0, segments: 32, rings: 8, material: Material::default()}
+    }
+}
+
+impl_primitive!(Capsule, capsule => format!("capsule:{}x{}:{}x{}", capsule.radius, capsule.height, capsule.segments, capsule.rings),
+    Mesh::capsule(capsule.radius, capsule.height, capsule.segments, capsule.rings));
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Unit normals, and every triangle with an area faces the way its vertex normals point
+    fn assert_surface(name: &str, mesh: &Mesh) {
+        assert_eq!(mesh.normals.len(), mesh.vertex_count(), "{}", name);
+        assert_eq!(mesh.uvs.len(), mesh.vertex_count(), "{}", name);
+
+        for normal in &mesh.normals {
+            assert!((Vec3::from_matrix(*normal).length() - 1.0).abs() < 1e-5, "{}: {:?}", name, normal);
+        }
+
+        for triangle in mesh.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| Vec3::from_matrix(mesh.positions[triangle[i] as usize]));
+            let face = (c - a).cross(b - a);
+            if face.length() < 1e-6 {
+                continue;
+            }
+            let normals = triangle.iter().fold(Vec3::ZERO, |sum, i| sum + Vec3::from_matrix(mesh.normals[*i as usize]));
+            assert!(face.dot(normals) > 0.0, "{}: triangle {:?} faces inward", name, triangle);
+        }
+    }
+
+    #[test]
+    fn vertex_and_triangle_counts() {
+        let meshes = [
+            ("uv sphere", Mesh::uv_sphere(1.0, 16, 8), 17 * 9, 2 * 16 * 8),
+            ("icosphere", Mesh::icosphere(1.0, 0), 12, 20),
+            ("icosphere 2", Mesh::icosphere(1.0, 2), 10 * 16 + 2, 20 * 16),
+            ("plane", Mesh::plane(Vec2::new(2.0, 1.0), 4), 5 * 5, 2 * 4 * 4),
+            // Side band plus two discs with a centre and a duplicated seam vertex
+            ("cylinder", Mesh::cylinder(0.5, 1.0, 12), 2 * 13 + 2 * 14, 2 * 12 + 2 * 12),
+            ("cone", Mesh::cone(0.5, 1.0, 12), 2 * 13 + 14, 2 * 12 + 12),
+            ("torus", Mesh::torus(1.0, 0.25, 12, 6), 13 * 7, 2 * 12 * 6),
+            ("capsule", Mesh::capsule(0.25, 1.0, 12, 4), 13 * 10, 2 * 12 * 9)
+        ];
+
+        for (name, mesh, vertices, triangles) in meshes {
+            assert_eq!((mesh.vertex_count(), mesh.triangle_count()), (vertices, triangles), "{}", name);
+            assert!(mesh.indices.iter().all(|i| (*i as usize) < mesh.vertex_count()), "{}", name);
+            assert_surface(name, &mesh);
+        }
+
+        // Too few segments are raised to the minimum
+        assert_eq!(Mesh::uv_sphere(1.0, 1, 1).vertex_count(), 4 * 3);
+    }
+
+    #[test]
+    fn surfaces_lie_where_they_should() {
+        for position in Mesh::icosphere(2.0, 1).positions.iter().chain(&Mesh::uv_sphere(2.0, 8, 6).positions) {
+            assert!((Vec3::from_matrix(*position).length() - 2.0).abs() < 1e-5);
+        }
+
+        let bounds = Mesh::capsule(0.25, 1.0, 8, 3).get_bounds();
+        assert!(bounds.min.approx_eq(Vec3::new(-0.25, -0.5, -0.25), 1e-5));
+        assert!(bounds.max.approx_eq(Vec3::new(0.25, 0.5, 0.25), 1e-5));
+
+        let plane = Mesh::plane(Vec2::new(2.0, 1.0), 1);
+        assert!(plane.positions.iter().all(|p| p[1] == 0.0));
+        assert!(plane.get_bounds().max.approx_eq(Vec3::new(1.0, 0.0, 0.5), 1e-6));
+    }
+}
@@ -0,0 +1,541 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::rc::Rc;
+
+use glium::backend::{Context, Facade};
+use glium::draw_parameters::{ClipControlDepth, PolygonOffset};
+use glium::framebuffer::SimpleFrameBuffer;
+use glium::texture::{DepthFormat, DepthTexture2d, MipmapsOption, Texture2d};
+use glium::uniforms::{DepthTextureComparison, MagnifySamplerFilter, MinifySamplerFilter, SamplerBehavior, SamplerWrapFunction, UniformValue, Uniforms};
+use glium::{DrawParameters, Rect, Surface, VertexBuffer, uniform};
+use image::RgbaImage;
+
+use super::debug::DebugVertex;
+use super::light::{LightData, MAX_LIGHTS};
+use super::material::Material;
+use super::math::{Frustum, Mat4};
+use super::mesh::{GliumBackend, MeshCache, MeshHandle, MeshLibrary};
+use super::shader::{default_shader_dir, ShaderCache, ShaderHandle};
+use super::shadow::ShadowData;
+use super::texture::{RenderTarget, TextureCache};
+
+#[derive(Copy, Clone)]
+pub struct Instance {
+    instance_model: [[f32; 4]; 4]
+}
+
+glium::implement_vertex!(Instance, instance_model);
+
+// Objects sharing one mesh and material, drawn together when instancing is on
+#[derive(Clone, Debug, PartialEq)]
+pub struct Batch {
+    pub mesh: MeshHandle,
+    pub material: Material,
+    pub models: Vec<Mat4>,
+    pub cast_shadows: bool,
+    pub receive_shadows: bool
+}
+
+// Uniforms shared by every draw call of a frame
+pub struct FrameUniforms {
+    pub perspective: [[f32; 4]; 4],
+    // Light falls along the view direction instead of from the eye
+    pub orthographic: bool,
+    // Depth in [0; 1] and the depth test passes for larger values, see Camera::set_reverse_z
+    pub reverse_z: bool,
+    // Pixels of the target drawn into, all of it when None
+    pub viewport: Option<Rect>,
+    // World space, see Renderer::cull_batches
+    pub frustum: Frustum,
+    pub view: [[f32; 4]; 4],
+    pub ambient: [f32; 3],
+    // Anything past MAX_LIGHTS is ignored
+    pub lights: Vec<LightData>,
+    pub shadow: Option<ShadowData>
+}
+
+// Textures of a material that are loaded, None for missing or broken ones
+struct MaterialMaps<'a> {
+    diffuse: Option<&'a Texture2d>,
+    specular: Option<&'a Texture2d>,
+    normal: Option<&'a Texture2d>,
+    sampler: SamplerBehavior
+}
+
+impl<'a> MaterialMaps<'a> {
+    fn new(textures: &'a TextureCache, material: &Material) -> Self {
+        let mipmaps = material.sampler.mipmaps;
+        let get = |path: &Option<String>| path.as_deref().and_then(|path| textures.get(path, mipmaps));
+
+        let paths = [&material.diffuse_texture, &material.specular_texture, &material.normal_texture];
+        let mut sampler = material.sampler;
+        sampler.mipmaps &= !paths.into_iter().flatten().any(|path| textures.is_target(path));
+
+        Self {
+            diffuse: get(&material.diffuse_texture),
+            specular: get(&material.specular_texture),
+            normal: get(&material.normal_texture),
+            sampler: sampler.get_behavior()
+        }
+    }
+}
+
+// Frame uniforms, the material and (outside instancing) the model matrix
+struct DrawUniforms<'a> {
+    frame: &'a FrameUniforms,
+    material: &'a Material,
+    maps: &'a MaterialMaps<'a>,
+    // Only set for batches that receive shadows
+    shadow_map: Option<&'a DepthTexture2d>,
+    model: Option<[[f32; 4]; 4]>
+}
+
+impl Uniforms for DrawUniforms<'_> {
+    fn visit_values<'a, F: FnMut(&str, UniformValue<'a>)>(&'a self, mut f: F) {
+        f("perspective", UniformValue::Mat4(self.frame.perspective));
+        f("view", UniformValue::Mat4(self.frame.view));
+        f("orthographic", UniformValue::Bool(self.frame.orthographic));
+        f("ambient", UniformValue::Vec3(self.frame.ambient));
+        if let Some(model) = self.model {
+            f("model", UniformValue::Mat4(model));
+        }
+
+        f("base_color", UniformValue::Vec4(self.material.base_color.get_matrix()));
+        f("specular", UniformValue::Vec3(self.material.specular.get_matrix()));
+        f("shininess", UniformValue::Float(self.material.shininess));
+        let maps = [("diffuse_map", self.maps.diffuse), ("specular_map", self.maps.specular), ("normal_map", self.maps.normal)];
+        for (name, texture) in maps {
+            f(&format!("has_{}", name), UniformValue::Bool(texture.is_some()));
+            if let Some(texture) = texture {
+                f(name, UniformValue::Texture2d(texture, Some(self.maps.sampler)));
+            }
+        }
+        for (name, value) in &self.material.uniforms {
+            f(name, value.get_value());
+        }
+
+        let shadow = self.frame.shadow.zip(self.shadow_map);
+        f("has_shadow_map", UniformValue::Bool(shadow.is_some()));
+        if let Some((shadow, shadow_map)) = shadow {
+            let sampler = SamplerBehavior {
+                wrap_function: (SamplerWrapFunction::Clamp, SamplerWrapFunction::Clamp, SamplerWrapFunction::Clamp),
+                minify_filter: MinifySamplerFilter::Linear,
+                magnify_filter: MagnifySamplerFilter::Linear,
+                depth_texture_comparison: Some(DepthTextureComparison::LessOrEqual),
+                ..Default::default()
+            };
+            f("shadow_map", UniformValue::DepthTexture2d(shadow_map, Some(sampler)));
+            f("shadow_matrix", UniformValue::Mat4(shadow.shadow_matrix.get_matrix()));
+            f("shadow_light", UniformValue::SignedInt(shadow.light as i32));
+            f("shadow_bias", UniformValue::Float(shadow.bias));
+            f("shadow_pcf_radius", UniformValue::SignedInt(shadow.pcf_radius as i32));
+        }
+
+        let lights = &self.frame.lights[..self.frame.lights.len().min(MAX_LIGHTS)];
+        f("light_count", UniformValue::SignedInt(lights.len() as i32));
+        for (i, light) in lights.iter().enumerate() {
+            f(&format!("lights[{}].kind", i), UniformValue::SignedInt(light.kind));
+            f(&format!("lights[{}].position", i), UniformValue::Vec3(light.position));
+            f(&format!("lights[{}].direction", i), UniformValue::Vec3(light.direction));
+            f(&format!("lights[{}].color", i), UniformValue::Vec3(light.color));
+            f(&format!("lights[{}].attenuation", i), UniformValue::Vec3(light.attenuation));
+            f(&format!("lights[{}].inner_cos", i), UniformValue::Float(light.inner_cos));
+            f(&format!("lights[{}].outer_cos", i), UniformValue::Float(light.outer_cos));
+        }
+    }
+}
+
+// Depth-only programs for the shadow pass
+struct ShadowPrograms {
+    single: ShaderHandle,
+    instanced: ShaderHandle
+}
+
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+pub struct RenderStats {
+    pub draw_calls: usize,
+    pub objects: usize,
+    pub instanced_batches: usize,
+    pub lights: usize,
+    // Lights beyond MAX_LIGHTS, not drawn
+    pub dropped_lights: usize,
+    // Objects drawn into the shadow map, its draw calls are part of `draw_calls`
+    pub shadow_casters: usize,
+    // Objects skipped for being outside the view, `objects` counts the drawn ones
+    pub culled: usize
+}
+
+// Draw times of every frame in a run, kept apart by whether instancing was on
+#[derive(Clone, Default, Debug)]
+pub struct FrameTimes {
+    instanced: Vec<f32>,
+    single: Vec<f32>
+}
+
+// Milliseconds over the frames of one mode
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TimeSummary {
+    pub frames: usize,
+    pub mean: f32,
+    pub p50: f32,
+    pub p95: f32,
+    pub p99: f32,
+    pub max: f32
+}
+
+impl fmt::Display for TimeSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} frames, mean {:.2} ms, p50 {:.2} ms, p95 {:.2} ms, p99 {:.2} ms, max {:.2} ms",
+            self.frames, self.mean, self.p50, self.p95, self.p99, self.max)
+    }
+}
+
+impl FrameTimes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, milliseconds: f32, instanced: bool) {
+        match instanced {
+            true => self.instanced.push(milliseconds),
+            false => self.single.push(milliseconds)
+        }
+    }
+
+    // None if no frame was drawn in that mode
+    pub fn get_summary(&self, instanced: bool) -> Option<TimeSummary> {
+        let mut times = match instanced {
+            true => self.instanced.clone(),
+            false => self.single.clone()
+        };
+        if times.is_empty() {
+            return None;
+        }
+        times.sort_by(f32::total_cmp);
+
+        // Nearest rank
+        let percentile = |p: f32| times[((p * times.len() as f32).ceil() as usize).clamp(1, times.len()) - 1];
+        Some(TimeSummary {
+            frames: times.len(),
+            mean: times.iter().sum::<f32>() / times.len() as f32,
+            p50: percentile(0.5),
+            p95: percentile(0.95),
+            p99: percentile(0.99),
+            max: times[times.len() - 1]
+        })
+    }
+}
+
+pub struct Renderer {
+    context: Rc<Context>,
+
+    shaders: ShaderCache,
+    program: ShaderHandle,
+    instanced_program: ShaderHandle,
+    line_program: ShaderHandle,
+    shadow_program: ShadowPrograms,
+    draw_parameters: DrawParameters<'static>,
+
+    meshes: MeshCache<GliumBackend>,
+    textures: TextureCache,
+    // Created on the first shadow pass, recreated when the resolution changes
+    shadow_map: Option<DepthTexture2d>,
+    // Float depth stand-in for the window, see World::draw_window
+    window_target: Option<RenderTarget>,
+
+    // Falls back to one draw call per object when off
+    pub instancing: bool,
+    pub culling: bool,
+    stats: RenderStats
+}
+
+impl Renderer {
+    // Works with the window's display as well as a headless context
+    pub fn new<F: Facade>(facade: &F, instancing: bool) -> Self {
+        Self::with_shader_dir(facade, instancing, &default_shader_dir())
+    }
+
+    // Shaders are read from `dir`, missing files fall back to the copies built into the binary
+    pub fn with_shader_dir<F: Facade>(facade: &F, instancing: bool, dir: &Path) -> Self {
+        let mut shaders = ShaderCache::new(facade, vec![("MAX_LIGHTS".to_string(), MAX_LIGHTS.to_string())]);
+        // Only fails if the copies built into the binary are broken too
+        let program = shaders.load(&dir.join("mesh.vert"), &dir.join("mesh.frag")).expect("built-in shader");
+        let instanced_program = shaders.load(&dir.join("instanced.vert"), &dir.join("mesh.frag")).expect("built-in shader");
+        let line_program = shaders.load(&dir.join("line.vert"), &dir.join("line.frag")).expect("built-in shader");
+        let shadow_program = ShadowPrograms {
+            single: shaders.load(&dir.join("mesh.vert"), &dir.join("shadow.frag")).expect("built-in shader"),
+            instanced: shaders.load(&dir.join("instanced.vert"), &dir.join("shadow.frag")).expect("built-in shader")
+        };
+
+        let draw_parameters = DrawParameters {
+            depth: glium::Depth {
+                test: glium::draw_parameters::DepthTest::IfLess,
+                write: true,
+                .. Default::default()
+            },
+            backface_culling: glium::draw_parameters::BackfaceCullingMode::CullClockwise,
+            .. Default::default()
+        };
+
+        let meshes = MeshCache::new(GliumBackend::new(facade));
+        let textures = TextureCache::new(facade);
+
+        Self {
+            context: facade.get_context().clone(),
+            shaders, program, instanced_program, line_program, shadow_program, draw_parameters,
+            meshes, textures, shadow_map: None, window_target: None,
+            instancing, culling: true, stats: RenderStats::default()
+        }
+    }
+
+    pub fn get_stats(&self) -> RenderStats {
+        self.stats
+    }
+
+    pub fn get_mesh_uploads(&self) -> usize {
+        self.meshes.get_uploads()
+    }
+
+    // Textures loaded so far, failed loads included
+    pub fn get_texture_count(&self) -> usize {
+        self.textures.len()
+    }
+
+    fn load_textures(&mut self, material: &Material) {
+        let paths = [&material.diffuse_texture, &material.specular_texture, &material.normal_texture];
+        for path in paths.into_iter().flatten() {
+            self.textures.load(path, material.sampler.mipmaps);
+        }
+    }
+
+    // Takes the named target out of the texture cache for a camera to draw into,
+    // see TextureCache::take_target. Materials can't sample it until it is put back.
+    pub fn take_target(&mut self, name: &str, width: u32, height: u32) -> Option<RenderTarget> {
+        self.textures.take_target(name, width, height)
+    }
+
+    pub fn insert_target(&mut self, name: &str, target: RenderTarget) {
+        self.textures.insert_target(name, target);
+    }
+
+    // Takes the window's stand-in out, (re)creating it at the given size
+    pub fn take_window_target(&mut self, width: u32, height: u32) -> Option<RenderTarget> {
+        let (width, height) = (width.max(1), height.max(1));
+        if let Some(target) = self.window_target.take().filter(|target| target.get_dimensions() == (width, height)) {
+            return Some(target);
+        }
+
+        match RenderTarget::new(&self.context, width, height) {
+            Ok(target) => Some(target),
+            Err(err) => {
+                println!("Can't create the window target: {}", err);
+                None
+            }
+        }
+    }
+
+    pub fn insert_window_target(&mut self, target: RenderTarget) {
+        self.window_target = Some(target);
+    }
+
+    // Images materials can use by name, uploaded when first drawn
+    pub fn add_images(&mut self, images: &HashMap<String, RgbaImage>) {
+        for (name, image) in images {
+            self.textures.add_image(name, image);
+        }
+    }
+
+    // Picks up edited shader files, see ShaderCache::reload_changed
+    pub fn reload_shaders(&mut self) -> usize {
+        self.shaders.reload_changed()
+    }
+
+    pub fn begin_frame(&mut self) {
+        self.stats = RenderStats::default();
+    }
+
+    // Records how many of the frame's lights fit into the shaders
+    pub fn use_lights(&mut self, uniforms: &FrameUniforms) {
+        self.stats.lights = uniforms.lights.len().min(MAX_LIGHTS);
+        self.stats.dropped_lights = uniforms.lights.len() - self.stats.lights;
+    }
+
+    // Renders the depth of every casting batch as seen from the shadow light.
+    // Has to run before the batches are drawn, which then sample the result.
+    pub fn draw_shadows(&mut self, batches: &[Batch], library: &MeshLibrary, shadow: Option<ShadowData>) {
+        let Some(shadow) = shadow else {
+            return;
+        };
+
+        let resolution = shadow.resolution;
+        if self.shadow_map.as_ref().map(|map| map.width()) != Some(resolution) {
+            self.shadow_map = match DepthTexture2d::empty_with_format(&self.context, DepthFormat::I24, MipmapsOption::NoMipmap, resolution, resolution) {
+                Ok(map) => Some(map),
+                Err(err) => {
+                    println!("Can't create shadow map: {}", err);
+                    None
+                }
+            };
+        }
+        let Some(shadow_map) = &self.shadow_map else {
+            return;
+        };
+
+        let Ok(mut target) = SimpleFrameBuffer::depth_only(&self.context, shadow_map) else {
+            return;
+        };
+        target.clear_depth(1.0);
+
+        // Pushes the stored depth back by the surface's slope across the filtered texels, so
+        // sloped receivers don't shadow themselves
+        let offset = (shadow.pcf_radius + 1) as f32;
+        let draw_parameters = DrawParameters {
+            polygon_offset: PolygonOffset {factor: offset, units: offset, fill: true, ..Default::default()},
+            ..self.draw_parameters.clone()
+        };
+
+        let perspective = shadow.light_matrix.get_matrix();
+        let view = Mat4::IDENTITY.get_matrix();
+        for batch in batches.iter().filter(|batch| batch.cast_shadows) {
+            self.stats.shadow_casters += batch.models.len();
+
+            let instances = match self.instancing && batch.models.len() > 1 {
+                true => self.create_instances(&batch.models),
+                false => None
+            };
+            let mesh = self.meshes.get(batch.mesh, library);
+            if let Some(per_instance) = instances.as_ref().and_then(|instances| instances.per_instance().ok()) {
+                target.draw((&mesh.vertices, per_instance), &mesh.indices, self.shaders.get(self.shadow_program.instanced),
+                &uniform! { perspective: perspective, view: view },
+                &draw_parameters).unwrap();
+                self.stats.draw_calls += 1;
+                continue;
+            }
+
+            for model in &batch.models {
+                target.draw(&mesh.vertices, &mesh.indices, self.shaders.get(self.shadow_program.single),
+                &uniform! { perspective: perspective, view: view, model: model.get_matrix() },
+                &draw_parameters).unwrap();
+            }
+            self.stats.draw_calls += batch.models.len();
+        }
+    }
+
+    // Drops the models outside the frustum and the batches left empty. The bounding
+    // sphere rejects most objects cheaply, the world-space box catches the rest.
+    pub fn cull_batches(&mut self, batches: &[Batch], library: &MeshLibrary, frustum: &Frustum) -> Vec<Batch> {
+        if !self.culling {
+            return batches.to_vec();
+        }
+
+        let mut visible = Vec::with_capacity(batches.len());
+        for batch in batches {
+            let (bounds, sphere) = (library.get_bounds(batch.mesh), library.get_bounding_sphere(batch.mesh));
+            let models: Vec<Mat4> = batch.models.iter().copied().filter(|model| {
+                frustum.intersects_sphere(&sphere.transform(*model)) && frustum.intersects_aabb(&bounds.transform(*model))
+            }).collect();
+
+            self.stats.culled += batch.models.len() - models.len();
+            if !models.is_empty() {
+                visible.push(Batch {mesh: batch.mesh, material: batch.material.clone(), models, cast_shadows: batch.cast_shadows, receive_shadows: batch.receive_shadows});
+            }
+        }
+        visible
+    }
+
+    pub fn draw_batch<S: Surface>(&mut self, frame: &mut S, batch: &Batch, library: &MeshLibrary, uniforms: &FrameUniforms) {
+        self.stats.objects += batch.models.len();
+        self.load_textures(&batch.material);
+
+        if self.instancing && batch.models.len() > 1 && self.draw_instanced(frame, batch, library, uniforms) {
+            self.stats.draw_calls += 1;
+            self.stats.instanced_batches += 1;
+            return;
+        }
+
+        let parameters = self.get_draw_parameters(uniforms);
+        let maps = MaterialMaps::new(&self.textures, &batch.material);
+        let shadow_map = self.shadow_map.as_ref().filter(|_| batch.receive_shadows);
+        let mesh = self.meshes.get(batch.mesh, library);
+        for model in &batch.models {
+            let uniforms = DrawUniforms {frame: uniforms, material: &batch.material, maps: &maps, shadow_map, model: Some(model.get_matrix())};
+            frame.draw(&mesh.vertices, &mesh.indices, self.shaders.get(self.program), &uniforms, &parameters).unwrap();
+        }
+        self.stats.draw_calls += batch.models.len();
+    }
+
+    // The shadow pass keeps the default, its depth is always cleared to 1
+    fn get_draw_parameters(&self, uniforms: &FrameUniforms) -> DrawParameters<'static> {
+        let mut parameters = self.draw_parameters.clone();
+        if uniforms.reverse_z {
+            // Clip space depth is written as is instead of being squeezed from [-1; 1],
+            // which would throw away the precision reverse-Z gains near 0
+            parameters.depth.test = glium::draw_parameters::DepthTest::IfMore;
+            parameters.clip_control_depth = ClipControlDepth::ZeroToOne;
+        }
+        parameters.viewport = uniforms.viewport;
+        parameters
+    }
+
+    // None if the context cannot instance
+    fn create_instances(&self, models: &[Mat4]) -> Option<VertexBuffer<Instance>> {
+        let instances: Vec<Instance> = models.iter().map(|model| Instance {instance_model: model.get_matrix()}).collect();
+        VertexBuffer::dynamic(&self.context, &instances).ok()
+    }
+
+    // False if the context cannot instance, the caller then draws one by one
+    fn draw_instanced<S: Surface>(&mut self, frame: &mut S, batch: &Batch, library: &MeshLibrary, uniforms: &FrameUniforms) -> bool {
+        let Some(instances) = self.create_instances(&batch.models) else {
+            return false;
+        };
+        let Ok(per_instance) = instances.per_instance() else {
+            return false;
+        };
+
+        let parameters = self.get_draw_parameters(uniforms);
+        let maps = MaterialMaps::new(&self.textures, &batch.material);
+        let shadow_map = self.shadow_map.as_ref().filter(|_| batch.receive_shadows);
+        let mesh = self.meshes.get(batch.mesh, library);
+        let uniforms = DrawUniforms {frame: uniforms, material: &batch.material, maps: &maps, shadow_map, model: None};
+        frame.draw((&mesh.vertices, per_instance), &mesh.indices, self.shaders.get(self.instanced_program), &uniforms, &parameters).unwrap();
+        true
+    }
+
+    // Line list, two vertices per line
+    pub fn draw_lines<S: Surface>(&mut self, frame: &mut S, lines: &[DebugVertex], uniforms: &FrameUniforms) {
+        if lines.is_empty() {
+            return;
+        }
+
+        let vertices = VertexBuffer::new(&self.context, lines).unwrap();
+        let indices = glium::index::NoIndices(glium::index::PrimitiveType::LinesList);
+
+        frame.draw(&vertices, indices, self.shaders.get(self.line_program),
+        &uniform! { view: uniforms.view, perspective: uniforms.perspective },
+        &self.get_draw_parameters(uniforms)).unwrap();
+        self.stats.draw_calls += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_times_summarize_each_mode() {
+        let mut times = FrameTimes::new();
+        for ms in 1..=100 {
+            times.add(ms as f32, true);
+        }
+        times.add(4.0, false);
+        times.add(2.0, false);
+
+        let instanced = times.get_summary(true).unwrap();
+        assert_eq!(instanced, TimeSummary {frames: 100, mean: 50.5, p50: 50.0, p95: 95.0, p99: 99.0, max: 100.0});
+        let single = times.get_summary(false).unwrap();
+        assert_eq!((single.frames, single.mean, single.p50, single.max), (2, 3.0, 2.0, 4.0));
+
+        assert!(FrameTimes::new().get_summary(true).is_none());
+    }
+}
@@ -0,0 +1,292 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::{Serialize, Deserialize};
+
+use super::math::Vec3;
+use super::scene::NodeId;
+use super::shadow::ShadowSettings;
+use super::view::Viewport;
+use super::{Camera, Object, World};
+
+// Bump when the layout of `SceneFile` changes
+pub const SCENE_VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    // Version found in the file
+    UnsupportedVersion(u32),
+    // Index of the node whose parent index is out of range or forms a cycle
+    InvalidParent(usize)
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Io(err) => write!(f, "scene io error: {}", err),
+            SceneError::Json(err) => write!(f, "scene json error: {}", err),
+            SceneError::UnsupportedVersion(version) => write!(f, "unsupported scene version {} (expected {})", version, SCENE_VERSION),
+            SceneError::InvalidParent(index) => write!(f, "node {} has an invalid parent", index)
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+impl From<io::Error> for SceneError {
+    fn from(err: io::Error) -> Self {
+        SceneError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for SceneError {
+    fn from(err: serde_json::Error) -> Self {
+        SceneError::Json(err)
+    }
+}
+
+#[derive(Serialize)]
+struct SceneFileRef<'a> {
+    version: u32,
+    name: &'a str,
+    global_light: Vec3,
+    global_light_color: Vec3,
+    ambient_color: (f32, f32, f32, f32),
+    camera: &'a Camera,
+    viewport: Viewport,
+    shadows: &'a ShadowSettings,
+    nodes: Vec<NodeRef<'a>>
+}
+
+#[derive(Serialize)]
+struct NodeRef<'a> {
+    parent: Option<usize>,
+    cast_shadows: bool,
+    receive_shadows: bool,
+    object: &'a dyn Object
+}
+
+#[derive(Deserialize)]
+struct SceneFile {
+    name: String,
+    global_light: Vec3,
+    // Missing in files saved while the global light was only a fallback
+    #[serde(default = "default_white")]
+    global_light_color: Vec3,
+    ambient_color: (f32, f32, f32, f32),
+    camera: Camera,
+    // Missing in files saved before viewports and shadows existed
+    #[serde(default)]
+    viewport: Viewport,
+    #[serde(default)]
+    shadows: ShadowSettings,
+    nodes: Vec<SavedNode>
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_white() -> Vec3 {
+    Vec3::ONE
+}
+
+#[derive(Deserialize)]
+struct SavedNode {
+    parent: Option<usize>,
+    #[serde(default = "default_true")]
+    cast_shadows: bool,
+    #[serde(default = "default_true")]
+    receive_shadows: bool,
+    object: Box<dyn Object>
+}
+
+#[derive(Deserialize)]
+struct Header {
+    version: u32
+}
+
+impl World {
+    pub fn to_json(&self) -> Result<String, SceneError> {
+        // Ids are not stable between sessions, so nodes are stored by their position in the file
+        let indices: HashMap<NodeId, usize> = self.get_nodes().enumerate().map(|(index, (id, _))| (id, index)).collect();
+
+        let nodes = self.get_nodes().map(|(_, node)| NodeRef {
+            parent: node.get_parent().map(|parent| indices[&parent]),
+            cast_shadows: node.casts_shadows(),
+            receive_shadows: node.receives_shadows(),
+            object: node.get_object()
+        }).collect();
+
+        let file = SceneFileRef {
+            version: SCENE_VERSION,
+            name: &self.name,
+            global_light: self.global_light,
+            global_light_color: self.global_light_color,
+            ambient_color: self.ambient_color,
+            camera: &self.camera,
+            viewport: self.viewport,
+            shadows: &self.shadows,
+            nodes
+        };
+
+        Ok(serde_json::to_string_pretty(&file)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, SceneError> {
+        let value: serde_json::Value = serde_json::from_str(json)?;
+
+        let header = Header::deserialize(&value)?;
+        if header.version != SCENE_VERSION {
+            return Err(SceneError::UnsupportedVersion(header.version));
+        }
+
+        let file = SceneFile::deserialize(value)?;
+
+        let mut world = World::new(&file.name);
+        world.global_light = file.global_light;
+        world.global_light_color = file.global_light_color;
+        world.ambient_color = file.ambient_color;
+        world.camera = file.camera;
+        world.viewport = file.viewport;
+        world.shadows = file.shadows;
+
+        let mut parents = Vec::with_capacity(file.nodes.len());
+        let mut ids = Vec::with_capacity(file.nodes.len());
+        for node in file.nodes {
+            parents.push(node.parent);
+            let id = world.add_boxed_object(node.object);
+            world.set_cast_shadows(id, node.cast_shadows);
+            world.set_receive_shadows(id, node.receive_shadows);
+            ids.push(id);
+        }
+
+        // Parents are linked after every node exists, so their order in the file does not matter
+        for (index, parent) in parents.into_iter().enumerate() {
+            if let Some(parent) = parent {
+                if parent >= ids.len() || !world.set_parent(ids[index], Some(ids[parent])) {
+                    return Err(SceneError::InvalidParent(index));
+                }
+            }
+        }
+
+        Ok(world)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), SceneError> {
+        fs::write(path, self.to_json()?)?;
+        Ok(())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, SceneError> {
+        let json = fs::read_to_string(path)?;
+        Self::from_json(&json)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::light::{Light, LightKind};
+    use super::super::view::{SceneCamera, ViewTarget};
+    use super::super::{Cuboid, Projection};
+
+    fn sample_world() -> World {
+        let mut world = World::new("Saved");
+        let base = world.add_object(Cuboid {size: Vec3::new(2.0, 1.0, 2.0), ..Cuboid::new("Base")});
+        let lamp = world.add_child(base, Light::spot("Lamp", Vec3::Y, -Vec3::Y, 0.2, 0.4)).unwrap();
+        world.add_child(lamp, SceneCamera::texture("Monitor Camera", "monitor", 64, 32));
+        world.add_object(Light::directional("Sun", Vec3::new(0.0, -1.0, 1.0)));
+        world.set_cast_shadows(base, false);
+
+        world.camera.position = Vec3::new(1.0, 2.0, -3.0);
+        world.camera.look_at(Vec3::ZERO);
+        world.camera.set_projection(Projection::Orthographic {size: 6.0});
+        world.camera.set_clip_planes(0.5, 40.0);
+        world.set_global_light(Vec3::new(-1.0, 2.0, 0.5), Vec3::new(1.0, 0.9, 0.8));
+        world.set_ambient_color((0.1, 0.2, 0.3, 1.0));
+        world
+    }
+
+    // Reparses the saved json after `edit` changed it
+    fn load_edited<F: FnOnce(&mut serde_json::Value)>(world: &World, edit: F) -> Result<World, SceneError> {
+        let mut value: serde_json::Value = serde_json::from_str(&world.to_json().unwrap()).unwrap();
+        edit(&mut value);
+        World::from_json(&value.to_string())
+    }
+
+    #[test]
+    fn round_trip_keeps_the_hierarchy() {
+        let world = sample_world();
+        let loaded = World::from_json(&world.to_json().unwrap()).unwrap();
+        assert_eq!(loaded.name, "Saved");
+        assert_eq!(loaded.object_count(), 4);
+
+        let base = loaded.find_by_name("Base").unwrap();
+        let lamp = loaded.find_by_name("Lamp").unwrap();
+        let monitor = loaded.find_by_name("Monitor Camera").unwrap();
+        let sun = loaded.find_by_name("Sun").unwrap();
+        assert_eq!(loaded.get_roots(), vec![base, sun]);
+        assert_eq!(loaded.get_children(base), &[lamp]);
+        assert_eq!(loaded.get_parent(monitor), Some(lamp));
+        assert!(!loaded.get_node(base).unwrap().casts_shadows());
+        assert!(loaded.get_node(sun).unwrap().casts_shadows());
+        assert_eq!(loaded.get_local_transform(lamp), world.get_local_transform(world.find_by_name("Lamp").unwrap()));
+    }
+
+    #[test]
+    fn round_trip_keeps_the_camera_and_lighting() {
+        let world = sample_world();
+        let loaded = World::from_json(&world.to_json().unwrap()).unwrap();
+
+        assert_eq!(loaded.camera.position, world.camera.position);
+        assert_eq!(loaded.camera.get_direction(), world.camera.get_direction());
+        assert_eq!(loaded.camera.get_projection(), Projection::Orthographic {size: 6.0});
+        assert_eq!(loaded.camera.get_clip_planes(), (0.5, 40.0));
+        assert_eq!(loaded.camera.get_fov(), world.camera.get_fov());
+        assert_eq!(loaded.get_global_light(), (Vec3::new(-1.0, 2.0, 0.5), Vec3::new(1.0, 0.9, 0.8)));
+        assert_eq!(loaded.get_ambient_color(), (0.1, 0.2, 0.3, 1.0));
+    }
+
+    #[test]
+    fn round_trip_keeps_the_object_kinds() {
+        let loaded = World::from_json(&sample_world().to_json().unwrap()).unwrap();
+
+        let base = loaded.get_as::<Cuboid>(loaded.find_by_name("Base").unwrap()).unwrap();
+        assert_eq!(base.size, Vec3::new(2.0, 1.0, 2.0));
+        let lamp = loaded.get_as::<Light>(loaded.find_by_name("Lamp").unwrap()).unwrap();
+        assert_eq!(lamp.kind, LightKind::Spot {inner_angle: 0.2, outer_angle: 0.4});
+        let sun = loaded.get_as::<Light>(loaded.find_by_name("Sun").unwrap()).unwrap();
+        assert_eq!(sun.kind, LightKind::Directional);
+        let monitor = loaded.get_as::<SceneCamera>(loaded.find_by_name("Monitor Camera").unwrap()).unwrap();
+        assert_eq!(monitor.target, ViewTarget::Texture {name: "monitor".to_string(), width: 64, height: 32});
+        assert_eq!(loaded.count_of_type::<Light>(), 2);
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        let result = load_edited(&sample_world(), |value| value["version"] = (SCENE_VERSION + 1).into());
+        assert!(matches!(result, Err(SceneError::UnsupportedVersion(version)) if version == SCENE_VERSION + 1));
+        assert!(matches!(World::from_json("{\"name\": \"No version\"}"), Err(SceneError::Json(_))));
+    }
+
+    #[test]
+    fn invalid_parents_are_rejected() {
+        let world = sample_world();
+
+        let out_of_range = load_edited(&world, |value| value["nodes"][1]["parent"] = 10.into());
+        assert!(matches!(out_of_range, Err(SceneError::InvalidParent(1))));
+
+        // Base under the monitor camera, which is below Base already
+        let cycle = load_edited(&world, |value| value["nodes"][0]["parent"] = 2.into());
+        assert!(matches!(cycle, Err(SceneError::InvalidParent(_))));
+
+        let own_parent = load_edited(&world, |value| value["nodes"][3]["parent"] = 3.into());
+        assert!(matches!(own_parent, Err(SceneError::InvalidParent(3))));
+    }
+}
@@ -0,0 +1,551 @@
+use super::math::{Aabb, Mat4};
+use super::mesh::MeshHandle;
+use super::transform::Transform;
+use super::{Object, World};
+
+// Generational handle: a removed node's id never matches whatever reuses its slot
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct NodeId {
+    index: u32,
+    generation: u32
+}
+
+// Object placed in the world hierarchy
+pub struct Node {
+    object: Box<dyn Object>,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+
+    world_matrix: Mat4,
+    // Resolved from the object's mesh key whenever the node is dirty
+    mesh: Option<MeshHandle>,
+    dirty: bool,
+
+    cast_shadows: bool,
+    receive_shadows: bool
+}
+
+impl Node {
+    fn new(object: Box<dyn Object>, parent: Option<NodeId>) -> Self {
+        Self {object, parent, children: Vec::new(), world_matrix: Mat4::IDENTITY, mesh: None, dirty: true, cast_shadows: true, receive_shadows: true}
+    }
+
+    pub fn get_object(&self) -> &dyn Object {
+        &*self.object
+    }
+
+    pub fn get_parent(&self) -> Option<NodeId> {
+        self.parent
+    }
+
+    pub fn get_children(&self) -> &[NodeId] {
+        &self.children
+    }
+
+    pub fn get_world_matrix(&self) -> Mat4 {
+        self.world_matrix
+    }
+
+    pub fn get_mesh(&self) -> Option<MeshHandle> {
+        self.mesh
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    pub fn casts_shadows(&self) -> bool {
+        self.cast_shadows
+    }
+
+    pub fn receives_shadows(&self) -> bool {
+        self.receive_shadows
+    }
+}
+
+#[derive(Default)]
+pub(super) struct Slot {
+    generation: u32,
+    node: Option<Node>
+}
+
+// Node storage
+impl World {
+    fn node(&self, id: NodeId) -> Option<&Node> {
+        let slot = self.nodes.get(id.index as usize)?;
+        match slot.generation == id.generation {
+            true => slot.node.as_ref(),
+            false => None
+        }
+    }
+
+    fn node_mut(&mut self, id: NodeId) -> Option<&mut Node> {
+        let slot = self.nodes.get_mut(id.index as usize)?;
+        match slot.generation == id.generation {
+            true => slot.node.as_mut(),
+            false => None
+        }
+    }
+
+    fn insert_node(&mut self, node: Node) -> NodeId {
+        match self.free_slots.pop() {
+            Some(index) => {
+                let slot = &mut self.nodes[index as usize];
+                slot.node = Some(node);
+                NodeId {index, generation: slot.generation}
+            },
+            None => {
+                let index = self.nodes.len() as u32;
+                self.nodes.push(Slot {generation: 0, node: Some(node)});
+                NodeId {index, generation: 0}
+            }
+        }
+    }
+
+    // Live nodes in slot order
+    pub fn get_nodes(&self) -> impl Iterator<Item = (NodeId, &Node)> {
+        self.nodes.iter().enumerate().filter_map(|(index, slot)| {
+            let id = NodeId {index: index as u32, generation: slot.generation};
+            slot.node.as_ref().map(|node| (id, node))
+        })
+    }
+
+    pub fn contains(&self, id: NodeId) -> bool {
+        self.node(id).is_some()
+    }
+}
+
+// Hierarchy
+impl World {
+    // Adds the object as a root node
+    pub fn add_object<T: Object + 'static>(&mut self, object: T) -> NodeId {
+        self.add_boxed_object(Box::new(object))
+    }
+
+    pub fn add_boxed_object(&mut self, object: Box<dyn Object>) -> NodeId {
+        self.insert_node(Node::new(object, None))
+    }
+
+    // Returns None if the parent no longer exists
+    pub fn add_child<T: Object + 'static>(&mut self, parent: NodeId, object: T) -> Option<NodeId> {
+        self.node(parent)?;
+
+        let id = self.insert_node(Node::new(Box::new(object), Some(parent)));
+        self.node_mut(parent)?.children.push(id);
+        Some(id)
+    }
+
+    // Removes the node together with everything below it and hands back its object
+    pub fn remove_object(&mut self, id: NodeId) -> Option<Box<dyn Object>> {
+        let parent = self.node(id)?.parent;
+        if let Some(parent) = parent.and_then(|parent| self.node_mut(parent)) {
+            parent.children.retain(|child| *child != id);
+        }
+
+        for descendant in self.get_descendants(id) {
+            self.free_node(descendant);
+        }
+        self.free_node(id).map(|node| node.object)
+    }
+
+    fn free_node(&mut self, id: NodeId) -> Option<Node> {
+        self.node(id)?;
+
+        let slot = &mut self.nodes[id.index as usize];
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free_slots.push(id.index);
+        slot.node.take()
+    }
+
+    // Slots are freed rather than dropped, so old ids never match the objects added next
+    pub fn clear_objects(&mut self) {
+        for index in 0..self.nodes.len() as u32 {
+            let generation = self.nodes[index as usize].generation;
+            self.free_node(NodeId {index, generation});
+        }
+        self.selected = None;
+    }
+
+    pub fn get_node(&self, id: NodeId) -> Option<&Node> {
+        self.node(id)
+    }
+
+    pub fn get_object(&self, id: NodeId) -> Option<&dyn Object> {
+        self.node(id).map(|node| &*node.object)
+    }
+
+    // Mutable access may change the local transform, so the node is marked dirty
+    pub fn get_object_mut(&mut self, id: NodeId) -> Option<&mut dyn Object> {
+        let node = self.node_mut(id)?;
+        node.dirty = true;
+        Some(&mut *node.object)
+    }
+
+    pub fn get_objects(&self) -> impl Iterator<Item = &dyn Object> {
+        self.get_nodes().map(|(_, node)| &*node.object)
+    }
+
+    // First object with the given name, in slot order
+    pub fn find_by_name(&self, name: &str) -> Option<NodeId> {
+        self.get_nodes().find(|(_, node)| node.object.get_name() == name).map(|(id, _)| id)
+    }
+
+    pub fn get_parent(&self, id: NodeId) -> Option<NodeId> {
+        self.node(id)?.parent
+    }
+
+    pub fn get_children(&self, id: NodeId) -> &[NodeId] {
+        self.node(id).map(|node| node.children.as_slice()).unwrap_or(&[])
+    }
+
+    pub fn get_roots(&self) -> Vec<NodeId> {
+        self.get_nodes().filter(|(_, node)| node.parent.is_none()).map(|(id, _)| id).collect()
+    }
+
+    // Moves the node under a new parent (or to the root with None), keeping its local transform.
+    // Returns false if either node is gone or the node would become its own ancestor.
+    pub fn set_parent(&mut self, id: NodeId, parent: Option<NodeId>) -> bool {
+        if !self.contains(id) {
+            return false;
+        }
+
+        if let Some(parent) = parent {
+            if !self.contains(parent) || self.is_ancestor(id, parent) {
+                return false;
+            }
+        }
+
+        let old_parent = self.node(id).and_then(|node| node.parent);
+        if let Some(old_parent) = old_parent.and_then(|old_parent| self.node_mut(old_parent)) {
+            old_parent.children.retain(|child| *child != id);
+        }
+
+        if let Some(parent) = parent.and_then(|parent| self.node_mut(parent)) {
+            parent.children.push(id);
+        }
+
+        if let Some(node) = self.node_mut(id) {
+            node.parent = parent;
+            node.dirty = true;
+        }
+        true
+    }
+
+    // True if `ancestor` is `id` itself or lies on the path from `id` to its root
+    pub fn is_ancestor(&self, ancestor: NodeId, id: NodeId) -> bool {
+        let mut current = Some(id);
+        while let Some(node) = current {
+            if node == ancestor {
+                return true;
+            }
+            current = self.get_parent(node);
+        }
+        false
+    }
+
+    pub fn get_local_transform(&self, id: NodeId) -> Option<Transform> {
+        self.get_object(id).map(|object| *object.transform())
+    }
+
+    pub fn set_local_transform(&mut self, id: NodeId, transform: Transform) {
+        if let Some(object) = self.get_object_mut(id) {
+            *object.transform_mut() = transform;
+        }
+    }
+
+    pub fn mark_dirty(&mut self, id: NodeId) {
+        if let Some(node) = self.node_mut(id) {
+            node.dirty = true;
+        }
+    }
+
+    // Whether the node shows up in shadow maps; children keep their own flag
+    pub fn set_cast_shadows(&mut self, id: NodeId, cast: bool) {
+        if let Some(node) = self.node_mut(id) {
+            node.cast_shadows = cast;
+        }
+    }
+
+    // Whether shadows darken the node
+    pub fn set_receive_shadows(&mut self, id: NodeId, receive: bool) {
+        if let Some(node) = self.node_mut(id) {
+            node.receive_shadows = receive;
+        }
+    }
+
+    // World matrix as of the last `update_transforms`
+    pub fn get_world_matrix(&self, id: NodeId) -> Option<Mat4> {
+        self.node(id).map(|node| node.world_matrix)
+    }
+
+    // Recomputes world matrices of dirty nodes and everything below them
+    pub fn update_transforms(&mut self) {
+        for root in self.get_roots() {
+            self.update_node(root, Mat4::IDENTITY, false);
+        }
+    }
+
+    fn update_node(&mut self, id: NodeId, parent_matrix: Mat4, parent_changed: bool) {
+        let Some(Slot {node: Some(node), ..}) = self.nodes.get_mut(id.index as usize) else {
+            return;
+        };
+
+        // Only the node itself can have changed its mesh
+        if node.dirty {
+            node.mesh = self.meshes.resolve(&*node.object);
+        }
+
+        let changed = node.dirty || parent_changed;
+        if changed {
+            node.world_matrix = parent_matrix * node.object.transform().get_matrix();
+            node.dirty = false;
+        }
+
+        let matrix = node.world_matrix;
+        for child in self.get_children(id).to_vec() {
+            self.update_node(child, matrix, changed);
+        }
+    }
+
+    // Depth-first traversal from the roots, children after their parent
+    pub fn traverse<F: FnMut(NodeId, &Node, usize)>(&self, mut f: F) {
+        let mut stack: Vec<(NodeId, usize)> = self.get_roots().into_iter().rev().map(|id| (id, 0)).collect();
+
+        while let Some((id, depth)) = stack.pop() {
+            let Some(node) = self.node(id) else {
+                continue;
+            };
+            f(id, node, depth);
+
+            stack.extend(node.children.iter().rev().map(|child| (*child, depth + 1)));
+        }
+    }
+
+    // All nodes below `id`, depth-first
+    pub fn get_descendants(&self, id: NodeId) -> Vec<NodeId> {
+        let mut result = Vec::new();
+        let mut stack: Vec<NodeId> = self.get_children(id).iter().rev().copied().collect();
+
+        while let Some(current) = stack.pop() {
+            result.push(current);
+            stack.extend(self.get_children(current).iter().rev());
+        }
+        result
+    }
+}
+
+// Queries
+impl World {
+    // World-space box around the node's mesh and the meshes below it, as of the last
+    // `update_transforms`. None if the node is gone or nothing under it has a mesh.
+    pub fn get_bounds(&self, id: NodeId) -> Option<Aabb> {
+        let mut ids = self.get_descendants(id);
+        ids.push(id);
+
+        ids.into_iter().filter_map(|id| {
+            let node = self.node(id)?;
+            Some(self.meshes.get_bounds(node.mesh?).transform(node.world_matrix))
+        }).reduce(|a, b| a.union(b))
+    }
+
+    // Box around every mesh in the world
+    pub fn get_scene_bounds(&self) -> Option<Aabb> {
+        self.get_nodes().filter_map(|(_, node)| {
+            Some(self.meshes.get_bounds(node.mesh?).transform(node.world_matrix))
+        }).reduce(|a, b| a.union(b))
+    }
+
+    // Selection for tools like the orbit camera, it isn't saved with the scene
+    pub fn select(&mut self, id: Option<NodeId>) {
+        self.selected = id;
+    }
+
+    // None once the selected node is removed
+    pub fn get_selected(&self) -> Option<NodeId> {
+        self.selected.filter(|id| self.contains(*id))
+    }
+
+    pub fn object_count(&self) -> usize {
+        self.nodes.len() - self.free_slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.object_count() == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (NodeId, &dyn Object)> {
+        self.get_nodes().map(|(id, node)| (id, &*node.object))
+    }
+
+    // Every yielded object is marked dirty since its transform may change
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (NodeId, &mut (dyn Object + 'static))> {
+        self.nodes.iter_mut().enumerate().filter_map(|(index, slot)| {
+            let id = NodeId {index: index as u32, generation: slot.generation};
+            slot.node.as_mut().map(|node| {
+                node.dirty = true;
+                (id, &mut *node.object)
+            })
+        })
+    }
+
+    pub fn find_all_by_name<'a>(&'a self, name: &'a str) -> impl Iterator<Item = NodeId> + 'a {
+        self.iter().filter(move |(_, object)| object.get_name() == name).map(|(id, _)| id)
+    }
+
+    pub fn remove_by_name(&mut self, name: &str) -> Option<Box<dyn Object>> {
+        let id = self.find_by_name(name)?;
+        self.remove_object(id)
+    }
+
+    // Removes every object `f` rejects, together with its children
+    pub fn retain<F: FnMut(NodeId, &dyn Object) -> bool>(&mut self, mut f: F) {
+        let removed: Vec<NodeId> = self.iter().filter(|(id, object)| !f(*id, *object)).map(|(id, _)| id).collect();
+        for id in removed {
+            self.remove_object(id);
+        }
+    }
+
+    pub fn get_as<T: Object + 'static>(&self, id: NodeId) -> Option<&T> {
+        self.get_object(id)?.as_any().downcast_ref::<T>()
+    }
+
+    pub fn get_as_mut<T: Object + 'static>(&mut self, id: NodeId) -> Option<&mut T> {
+        self.get_object_mut(id)?.as_any_mut().downcast_mut::<T>()
+    }
+
+    pub fn objects_of_type<T: Object + 'static>(&self) -> impl Iterator<Item = (NodeId, &T)> {
+        self.iter().filter_map(|(id, object)| object.as_any().downcast_ref::<T>().map(|object| (id, object)))
+    }
+
+    // Only matching objects are marked dirty
+    pub fn objects_of_type_mut<T: Object + 'static>(&mut self) -> impl Iterator<Item = (NodeId, &mut T)> {
+        self.nodes.iter_mut().enumerate().filter_map(|(index, slot)| {
+            let id = NodeId {index: index as u32, generation: slot.generation};
+            let node = slot.node.as_mut()?;
+            if !(*node.object).as_any().is::<T>() {
+                return None;
+            }
+
+            node.dirty = true;
+            (*node.object).as_any_mut().downcast_mut::<T>().map(|object| (id, object))
+        })
+    }
+
+    pub fn count_of_type<T: Object + 'static>(&self) -> usize {
+        self.objects_of_type::<T>().count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::math::Vec3;
+    use super::super::Cuboid;
+
+    fn cuboid_at(name: &str, translation: Vec3) -> Cuboid {
+        Cuboid {transform: Transform::from_translation(translation), ..Cuboid::new(name)}
+    }
+
+    fn position(world: &World, id: NodeId) -> Vec3 {
+        world.get_world_matrix(id).unwrap().transform_point(Vec3::ZERO)
+    }
+
+    #[test]
+    fn reparenting_keeps_the_local_transform() {
+        let mut world = World::new("Hierarchy");
+        let a = world.add_object(cuboid_at("A", Vec3::new(1.0, 0.0, 0.0)));
+        let b = world.add_object(cuboid_at("B", Vec3::new(0.0, 5.0, 0.0)));
+        let child = world.add_child(a, cuboid_at("Child", Vec3::new(0.0, 0.0, 1.0))).unwrap();
+        world.update_transforms();
+        assert!(position(&world, child).approx_eq(Vec3::new(1.0, 0.0, 1.0), 1e-6));
+
+        assert!(world.set_parent(child, Some(b)));
+        assert!(world.get_children(a).is_empty());
+        assert_eq!(world.get_children(b), &[child]);
+        assert_eq!(world.get_parent(child), Some(b));
+        world.update_transforms();
+        assert!(position(&world, child).approx_eq(Vec3::new(0.0, 5.0, 1.0), 1e-6));
+
+        assert!(world.set_parent(child, None));
+        assert_eq!(world.get_roots().len(), 3);
+        world.update_transforms();
+        assert!(position(&world, child).approx_eq(Vec3::new(0.0, 0.0, 1.0), 1e-6));
+
+        // Removing a parent takes its children along
+        world.set_parent(child, Some(a));
+        assert!(world.remove_object(a).is_some());
+        assert!(!world.contains(child));
+        assert_eq!(world.object_count(), 1);
+    }
+
+    #[test]
+    fn cycles_are_rejected() {
+        let mut world = World::new("Hierarchy");
+        let root = world.add_object(Cuboid::new("Root"));
+        let child = world.add_child(root, Cuboid::new("Child")).unwrap();
+        let grandchild = world.add_child(child, Cuboid::new("Grandchild")).unwrap();
+
+        assert!(!world.set_parent(root, Some(grandchild)));
+        assert!(!world.set_parent(child, Some(child)));
+        assert_eq!(world.get_parent(root), None);
+        assert_eq!(world.get_parent(child), Some(root));
+        assert!(world.is_ancestor(root, grandchild));
+        assert!(!world.is_ancestor(grandchild, root));
+        assert_eq!(world.get_descendants(root), vec![child, grandchild]);
+
+        // Gone nodes can neither move nor take children
+        world.remove_object(grandchild);
+        assert!(!world.set_parent(grandchild, None));
+        assert!(!world.set_parent(child, Some(grandchild)));
+        assert!(world.add_child(grandchild, Cuboid::new("Orphan")).is_none());
+    }
+
+    #[test]
+    fn cleared_ids_stay_dead() {
+        let mut world = World::new("Hierarchy");
+        let root = world.add_object(Cuboid::new("Root"));
+        let child = world.add_child(root, Cuboid::new("Child")).unwrap();
+        world.remove_object(child);
+        world.select(Some(root));
+
+        world.clear_objects();
+        assert!(world.is_empty());
+        assert_eq!(world.get_selected(), None);
+
+        // The new objects reuse the slots but not the ids
+        let first = world.add_object(Cuboid::new("First"));
+        let second = world.add_object(Cuboid::new("Second"));
+        assert!(!world.contains(root));
+        assert!(!world.contains(child));
+        assert!(world.get_object(root).is_none());
+        assert_ne!(first, root);
+        assert_ne!(second, child);
+        assert_eq!(world.object_count(), 2);
+        assert_eq!(world.nodes.len(), 2);
+    }
+
+    #[test]
+    fn dirty_parents_move_their_children() {
+        let mut world = World::new("Hierarchy");
+        let root = world.add_object(Cuboid::new("Root"));
+        let child = world.add_child(root, cuboid_at("Child", Vec3::X)).unwrap();
+        assert!(world.get_node(child).unwrap().is_dirty());
+        world.update_transforms();
+        assert!(!world.get_node(root).unwrap().is_dirty());
+        assert!(!world.get_node(child).unwrap().is_dirty());
+        assert!(world.get_node(child).unwrap().get_mesh().is_some());
+
+        // Only the parent is marked, the child still follows it
+        world.set_local_transform(root, Transform::from_translation(Vec3::new(0.0, 2.0, 0.0)));
+        assert!(world.get_node(root).unwrap().is_dirty());
+        assert!(!world.get_node(child).unwrap().is_dirty());
+        world.update_transforms();
+        assert!(position(&world, child).approx_eq(Vec3::new(1.0, 2.0, 0.0), 1e-6));
+
+        // Matrices only change on update
+        world.get_as_mut::<Cuboid>(child).unwrap().transform.translation = Vec3::ZERO;
+        assert!(position(&world, child).approx_eq(Vec3::new(1.0, 2.0, 0.0), 1e-6));
+        world.update_transforms();
+        assert!(position(&world, child).approx_eq(Vec3::new(0.0, 2.0, 0.0), 1e-6));
+    }
+}
@@ -0,0 +1,425 @@
+// Shader files with #include, compiled into programs that reload when the files change
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime};
+
+use glium::backend::{Context, Facade};
+use glium::program::ProgramCreationError;
+use glium::Program;
+
+// Overrides where the shader files are read from
+pub const SHADER_DIR_VAR: &str = "DENGINE_SHADER_DIR";
+
+// $DENGINE_SHADER_DIR, or "shaders" in the working directory
+pub fn default_shader_dir() -> PathBuf {
+    std::env::var_os(SHADER_DIR_VAR).map(PathBuf::from).unwrap_or_else(|| PathBuf::from("shaders"))
+}
+
+// Copy of the shader directory inside the binary, used when the files can't be read or don't compile
+const BUILTIN: &[(&str, &str)] = &[
+    ("mesh.vert", include_str!("../shaders/mesh.vert")),
+    ("mesh.frag", include_str!("../shaders/mesh.frag")),
+    ("instanced.vert", include_str!("../shaders/instanced.vert")),
+    ("lighting.glsl", include_str!("../shaders/lighting.glsl")),
+    ("shadow.glsl", include_str!("../shaders/shadow.glsl")),
+    ("shadow.frag", include_str!("../shaders/shadow.frag")),
+    ("line.vert", include_str!("../shaders/line.vert")),
+    ("line.frag", include_str!("../shaders/line.frag"))
+];
+
+// How often the files are checked for changes
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug)]
+pub enum ShaderError {
+    Io(PathBuf, io::Error),
+    // Includes that lead back to a file already being included
+    IncludeCycle(Vec<PathBuf>),
+    // File and line (1-based) of an #include without a quoted path
+    BadInclude(PathBuf, usize),
+    // Driver log, locations already point at the original files
+    Compile(String),
+    Link(String),
+    Program(ProgramCreationError)
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShaderError::Io(path, err) => write!(f, "can't read {}: {}", path.display(), err),
+            ShaderError::IncludeCycle(chain) => {
+                let chain: Vec<String> = chain.iter().map(|path| path.display().to_string()).collect();
+                write!(f, "include cycle: {}", chain.join(" -> "))
+            },
+            ShaderError::BadInclude(path, line) => write!(f, "{}:{}: expected #include \"file\"", path.display(), line),
+            ShaderError::Compile(log) => write!(f, "compile error:\n{}", log),
+            ShaderError::Link(log) => write!(f, "link error:\n{}", log),
+            ShaderError::Program(err) => write!(f, "{}", err)
+        }
+    }
+}
+
+impl std::error::Error for ShaderError {}
+
+// Source with includes pasted in, remembering where every line came from
+pub struct ShaderSource {
+    pub code: String,
+    // Every file read, the main one first
+    pub files: Vec<PathBuf>,
+    // File index and 1-based line for each line of `code`, None for generated lines
+    lines: Vec<Option<(usize, usize)>>
+}
+
+impl ShaderSource {
+    // "file:line" of a 1-based line of `code`
+    pub fn get_location(&self, line: usize) -> Option<String> {
+        let (file, line) = (*self.lines.get(line.checked_sub(1)?)?)?;
+        Some(format!("{}:{}", self.files[file].display(), line))
+    }
+
+    // Rewrites the first `0:12`, `0:12(5)` or `0(12)` location of every log line
+    pub fn map_log(&self, log: &str) -> String {
+        log.lines().map(|line| self.map_log_line(line)).collect::<Vec<_>>().join("\n")
+    }
+
+    fn map_log_line(&self, line: &str) -> String {
+        let bytes = line.as_bytes();
+        let digits = |from: usize| bytes[from..].iter().take_while(|b| b.is_ascii_digit()).count();
+
+        let mut start = 0;
+        while start < bytes.len() {
+            let first = digits(start);
+            let at_word_start = start == 0 || !bytes[start - 1].is_ascii_alphanumeric();
+            if first == 0 || !at_word_start {
+                start += 1;
+                continue;
+            }
+
+            let separator = start + first;
+            if let Some(b':' | b'(') = bytes.get(separator) {
+                let number = digits(separator + 1);
+                let mut end = separator + 1 + number;
+                if bytes[separator] == b'(' && bytes.get(end) == Some(&b')') {
+                    end += 1;
+                }
+
+                let location = line[separator + 1..separator + 1 + number].parse().ok().and_then(|n| self.get_location(n));
+                if let Some(location) = location {
+                    return format!("{}{}{}", &line[..start], location, &line[end..]);
+                }
+            }
+            start += first;
+        }
+        line.to_string()
+    }
+}
+
+// Pastes `#include "file"` lines (relative to the including file) and puts the defines right after #version
+pub fn preprocess<F>(path: &Path, defines: &[(String, String)], mut read: F) -> Result<ShaderSource, ShaderError>
+where F: FnMut(&Path) -> io::Result<String> {
+    let mut source = ShaderSource {code: String::new(), files: Vec::new(), lines: Vec::new()};
+    let mut stack = Vec::new();
+    include_file(path, &mut source, &mut stack, &mut read)?;
+
+    // GLSL wants #version before anything else
+    let version = source.code.lines().position(|line| line.trim_start().starts_with("#version"));
+    let insert_at = version.map(|line| line + 1).unwrap_or(0);
+
+    let mut lines: Vec<&str> = source.code.lines().collect();
+    let generated: Vec<String> = defines.iter().map(|(name, value)| format!("#define {} {}", name, value)).collect();
+    lines.splice(insert_at..insert_at, generated.iter().map(|line| line.as_str()));
+    source.lines.splice(insert_at..insert_at, generated.iter().map(|_| None));
+
+    let mut code = lines.join("\n");
+    code.push('\n');
+    source.code = code;
+    Ok(source)
+}
+
+fn include_file<F>(path: &Path, source: &mut ShaderSource, stack: &mut Vec<PathBuf>, read: &mut F) -> Result<(), ShaderError>
+where F: FnMut(&Path) -> io::Result<String> {
+    if stack.iter().any(|other| other == path) {
+        let mut chain = stack.clone();
+        chain.push(path.to_path_buf());
+        return Err(ShaderError::IncludeCycle(chain));
+    }
+
+    let text = read(path).map_err(|err| ShaderError::Io(path.to_path_buf(), err))?;
+    let file = source.files.len();
+    source.files.push(path.to_path_buf());
+    stack.push(path.to_path_buf());
+
+    for (number, line) in text.lines().enumerate() {
+        let Some(argument) = line.trim().strip_prefix("#include") else {
+            source.code.push_str(line);
+            source.code.push('\n');
+            source.lines.push(Some((file, number + 1)));
+            continue;
+        };
+
+        let name = argument.trim().strip_prefix('"').and_then(|rest| rest.strip_suffix('"'));
+        let Some(name) = name else {
+            return Err(ShaderError::BadInclude(path.to_path_buf(), number + 1));
+        };
+        let included = path.parent().unwrap_or(Path::new("")).join(name);
+        include_file(&included, source, stack, read)?;
+    }
+
+    stack.pop();
+    Ok(())
+}
+
+fn read_builtin(path: &Path) -> io::Result<String> {
+    let name = path.file_name().and_then(|name| name.to_str()).unwrap_or("");
+    match BUILTIN.iter().find(|(file, _)| *file == name) {
+        Some((_, text)) => Ok(text.to_string()),
+        None => Err(io::Error::new(io::ErrorKind::NotFound, "no built-in shader with this name"))
+    }
+}
+
+// Program plus every file it was built from
+fn compile<F>(context: &Rc<Context>, vertex: &Path, fragment: &Path, defines: &[(String, String)], mut read: F) -> Result<(Program, Vec<PathBuf>), ShaderError>
+where F: FnMut(&Path) -> io::Result<String> {
+    let vertex = preprocess(vertex, defines, &mut read)?;
+    let fragment = preprocess(fragment, defines, &mut read)?;
+
+    let program = Program::from_source(context, &vertex.code, &fragment.code, None).map_err(|err| match err {
+        ProgramCreationError::CompilationError(log, glium::program::ShaderType::Vertex) => ShaderError::Compile(vertex.map_log(&log)),
+        ProgramCreationError::CompilationError(log, _) => ShaderError::Compile(fragment.map_log(&log)),
+        ProgramCreationError::LinkingError(log) => ShaderError::Link(map_link_log(&vertex, &fragment, &log)),
+        err => ShaderError::Program(err)
+    })?;
+
+    let mut files = vertex.files;
+    files.extend(fragment.files);
+    files.sort();
+    files.dedup();
+    Ok((program, files))
+}
+
+// Link logs can point into either stage. Lines after a "vertex ..." or "fragment ..." heading are
+// mapped with that stage's source, lines before any heading with the vertex one.
+fn map_link_log(vertex: &ShaderSource, fragment: &ShaderSource, log: &str) -> String {
+    let mut source = vertex;
+    log.lines().map(|line| {
+        let heading = line.trim_start().to_ascii_lowercase();
+        if heading.starts_with("vertex") {
+            source = vertex;
+        } else if heading.starts_with("fragment") {
+            source = fragment;
+        }
+        source.map_log_line(line)
+    }).collect::<Vec<_>>().join("\n")
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct ShaderHandle(usize);
+
+struct ShaderEntry {
+    vertex: PathBuf,
+    fragment: PathBuf,
+    program: Program,
+    // Files read for the program with their modification time at that point
+    files: Vec<(PathBuf, Option<SystemTime>)>
+}
+
+impl ShaderEntry {
+    fn is_changed(&self) -> bool {
+        self.files.iter().any(|(path, time)| modified(path) != *time)
+    }
+
+    fn set_files(&mut self, files: Vec<PathBuf>) {
+        self.files = files.into_iter().map(|path| {
+            let time = modified(&path);
+            (path, time)
+        }).collect();
+    }
+}
+
+// Programs keyed by their (vertex, fragment) paths
+pub struct ShaderCache {
+    context: Rc<Context>,
+    // `#define`d in every shader
+    defines: Vec<(String, String)>,
+
+    entries: Vec<ShaderEntry>,
+    handles: HashMap<(PathBuf, PathBuf), ShaderHandle>,
+    last_poll: Instant
+}
+
+impl ShaderCache {
+    pub fn new<F: Facade>(facade: &F, defines: Vec<(String, String)>) -> Self {
+        Self {context: facade.get_context().clone(), defines, entries: Vec::new(), handles: HashMap::new(), last_poll: Instant::now()}
+    }
+
+    // Compiles the pair the first time, later calls return the same handle.
+    // If the files fail, the error is printed and the built-in copy of the same names is used
+    // until the files are fixed; the error is only returned when there is no such copy.
+    pub fn load(&mut self, vertex: &Path, fragment: &Path) -> Result<ShaderHandle, ShaderError> {
+        let key = (vertex.to_path_buf(), fragment.to_path_buf());
+        if let Some(handle) = self.handles.get(&key) {
+            return Ok(*handle);
+        }
+
+        let mut read = Vec::new();
+        let result = compile(&self.context, vertex, fragment, &self.defines, |path| {
+            read.push(path.to_path_buf());
+            fs::read_to_string(path)
+        });
+        let (program, files) = match result {
+            Ok(compiled) => compiled,
+            Err(err) => match compile(&self.context, vertex, fragment, &self.defines, read_builtin) {
+                Ok((program, builtin_files)) => {
+                    println!("Can't load shader {} + {}, using the built-in one: {}", vertex.display(), fragment.display(), err);
+                    // Includes resolve next to the requested paths either way, so this watches the
+                    // files the disk copies read as well as those the built-in ones would have
+                    read.extend(builtin_files);
+                    read.sort();
+                    read.dedup();
+                    (program, read)
+                },
+                Err(_) => return Err(err)
+            }
+        };
+
+        let mut entry = ShaderEntry {vertex: key.0.clone(), fragment: key.1.clone(), program, files: Vec::new()};
+        entry.set_files(files);
+
+        let handle = ShaderHandle(self.entries.len());
+        self.entries.push(entry);
+        self.handles.insert(key, handle);
+        Ok(handle)
+    }
+
+    pub fn get(&self, handle: ShaderHandle) -> &Program {
+        &self.entries[handle.0].program
+    }
+
+    // Recompiles from disk, the old program stays in use if that fails
+    pub fn reload(&mut self, handle: ShaderHandle) -> Result<(), ShaderError> {
+        let entry = &mut self.entries[handle.0];
+        let mut files = Vec::new();
+        let result = compile(&self.context, &entry.vertex, &entry.fragment, &self.defines, |path| {
+            files.push(path.to_path_buf());
+            fs::read_to_string(path)
+        });
+
+        // Watch whatever was read either way, so a broken file is retried once it changes again
+        files.extend(entry.files.drain(..).map(|(path, _)| path));
+        files.sort();
+        files.dedup();
+        entry.set_files(files);
+
+        entry.program = result?.0;
+        Ok(())
+    }
+
+    // Reloads programs whose files changed since they were read, checking at most every POLL_INTERVAL.
+    // Returns how many were reloaded.
+    pub fn reload_changed(&mut self) -> usize {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return 0;
+        }
+        self.last_poll = Instant::now();
+
+        let mut reloaded = 0;
+        for index in 0..self.entries.len() {
+            if !self.entries[index].is_changed() {
+                continue;
+            }
+
+            let entry = &self.entries[index];
+            let name = format!("{} + {}", entry.vertex.display(), entry.fragment.display());
+            match self.reload(ShaderHandle(index)) {
+                Ok(()) => {
+                    println!("Reloaded shader {}", name);
+                    reloaded += 1;
+                },
+                Err(err) => println!("Can't reload shader {}, keeping the previous one: {}", name, err)
+            }
+        }
+        reloaded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::headless::test_renderer;
+
+    fn read_from<'a>(files: &'a [(&'a str, &'a str)]) -> impl FnMut(&Path) -> io::Result<String> + 'a {
+        move |path| match files.iter().find(|(name, _)| Path::new(name) == path) {
+            Some((_, text)) => Ok(text.to_string()),
+            None => Err(io::Error::new(io::ErrorKind::NotFound, "missing"))
+        }
+    }
+
+    #[test]
+    fn includes_keep_their_line_numbers() {
+        let files = [
+            ("dir/main.vert", "#version 140\n#include \"common.glsl\"\nvoid main() {}"),
+            ("dir/common.glsl", "float a;\nfloat b;")
+        ];
+        let defines = [("MAX_LIGHTS".to_string(), "8".to_string())];
+        let source = preprocess(Path::new("dir/main.vert"), &defines, read_from(&files)).unwrap();
+
+        assert_eq!(source.code, "#version 140\n#define MAX_LIGHTS 8\nfloat a;\nfloat b;\nvoid main() {}\n");
+        assert_eq!(source.get_location(2), None);
+        assert_eq!(source.get_location(4), Some(format!("{}:2", Path::new("dir/common.glsl").display())));
+        assert_eq!(source.map_log("0:5(3): error: oops"), format!("{}:3(3): error: oops", Path::new("dir/main.vert").display()));
+    }
+
+    #[test]
+    fn include_cycles_are_errors() {
+        let files = [("a.glsl", "#include \"b.glsl\""), ("b.glsl", "#include \"a.glsl\"")];
+        let result = preprocess(Path::new("a.glsl"), &[], read_from(&files));
+        assert!(matches!(result, Err(ShaderError::IncludeCycle(chain)) if chain.len() == 3));
+    }
+
+    #[test]
+    fn fallbacks_watch_the_included_files() {
+        let Some(headless) = test_renderer("fallbacks_watch_the_included_files") else {
+            return;
+        };
+
+        let dir = std::env::temp_dir().join(format!("dengine_shader_test_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for (name, text) in BUILTIN {
+            fs::write(dir.join(name), text).unwrap();
+        }
+        let lighting = dir.join("lighting.glsl");
+        fs::write(&lighting, "this is not glsl").unwrap();
+
+        let defines = vec![("MAX_LIGHTS".to_string(), "8".to_string())];
+        let mut cache = ShaderCache::new(&headless, defines);
+        let handle = cache.load(&dir.join("mesh.vert"), &dir.join("mesh.frag")).unwrap();
+        assert!(cache.entries[handle.0].files.iter().any(|(path, _)| *path == lighting));
+
+        // Fixing the include is picked up on the next poll
+        let text = read_builtin(&lighting).unwrap();
+        fs::write(&lighting, text).unwrap();
+        fs::File::options().write(true).open(&lighting).unwrap().set_modified(SystemTime::UNIX_EPOCH).unwrap();
+        cache.last_poll -= POLL_INTERVAL;
+        assert_eq!(cache.reload_changed(), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn link_logs_are_mapped_per_stage() {
+        let files = [("v.vert", "#version 140\nout vec3 a;\nvoid main() {}"), ("f.frag", "#version 140\n\nin vec2 a;\nvoid main() {}")];
+        let vertex = preprocess(Path::new("v.vert"), &[], read_from(&files)).unwrap();
+        let fragment = preprocess(Path::new("f.frag"), &[], read_from(&files)).unwrap();
+
+        let log = "0(2) : error: type mismatch\nVertex info\n0(2) : declared here\nFragment info\n0(3) : used here";
+        assert_eq!(map_link_log(&vertex, &fragment, log), "v.vert:2 : error: type mismatch\nVertex info\nv.vert:2 : declared here\nFragment info\nf.frag:3 : used here");
+    }
+}
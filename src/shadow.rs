@@ -0,0 +1,159 @@
+// Shadow map for one directional light, fitted around everything drawn
+use serde::{Serialize, Deserialize};
+
+use super::light::{Light, LightKind, MAX_LIGHTS};
+use super::math::{Aabb, Mat4, Vec3};
+use super::renderer::Batch;
+use super::World;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ShadowSettings {
+    pub enabled: bool,
+    // Width and height of the shadow map in texels
+    pub resolution: u32,
+    // Depth offset against shadow acne, in shadow map depth ([0; 1] across the scene).
+    // The full amount is used at grazing angles, a tenth where the surface faces the light.
+    pub bias: f32,
+    // Texels filtered on each side of a pixel, 0 for hard edges
+    pub pcf_radius: u32
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        Self {enabled: true, resolution: 2048, bias: 0.005, pcf_radius: 1}
+    }
+}
+
+// Everything the passes need to render and sample the shadow map
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShadowData {
+    // World space to the light's clip space, for the shadow pass
+    pub light_matrix: Mat4,
+    // View space to shadow map coordinates and depth, all in [0; 1]. Starts from world space
+    // until `for_view` picks the view.
+    pub shadow_matrix: Mat4,
+    // Index of the casting light in `FrameUniforms::lights`
+    pub light: usize,
+    pub resolution: u32,
+    pub bias: f32,
+    pub pcf_radius: u32
+}
+
+impl ShadowData {
+    // The same shadow map sampled from the view with this world-to-view matrix
+    pub fn for_view(&self, view: Mat4) -> Option<Self> {
+        // Clip space [-1; 1] to texture coordinates and depth in [0; 1]
+        let to_texture = Mat4::translation(Vec3::splat(0.5)) * Mat4::scale(Vec3::splat(0.5));
+        Some(Self {shadow_matrix: to_texture * self.light_matrix * view.inverse()?, ..*self})
+    }
+}
+
+impl World {
+    pub fn get_shadows(&self) -> &ShadowSettings {
+        &self.shadows
+    }
+
+    pub fn get_shadows_mut(&mut self) -> &mut ShadowSettings {
+        &mut self.shadows
+    }
+
+    // The global light, or the first directional light object when it's off, as its index in
+    // `collect_lights` and the direction its rays travel
+    fn get_shadow_light(&self) -> Option<(usize, Vec3)> {
+        if let Some(global) = self.get_global_light_object() {
+            // The global light comes first in `collect_lights`
+            return Some((0, global.transform.forward()));
+        }
+
+        let lights = self.get_nodes().filter_map(|(_, node)| {
+            let light = node.get_object().as_any().downcast_ref::<Light>()?;
+            Some((light, node.get_world_matrix()))
+        });
+        // Point and spot lights don't cast shadows
+        let (index, (_, matrix)) = lights.enumerate().find(|(_, (light, _))| light.kind == LightKind::Directional)?;
+        match index < MAX_LIGHTS {
+            true => Some((index, matrix.transform_vector(Vec3::Z).normalize())),
+            false => None
+        }
+    }
+
+    // Shadow map of the frame, shared by all its views. None when shadows are off, nothing is
+    // drawn or there is no light to cast them.
+    pub(super) fn get_shadow_data(&self, batches: &[Batch]) -> Option<ShadowData> {
+        if !self.shadows.enabled || batches.is_empty() {
+            return None;
+        }
+        let (light, direction) = self.get_shadow_light()?;
+
+        let up = match direction.cross(Vec3::Y).length_squared() < 1e-6 {
+            true => Vec3::Z,
+            false => Vec3::Y
+        };
+        let light_view = Mat4::look_to(Vec3::ZERO, direction, up);
+
+        // Fitting the whole scene keeps shadows from swimming as the camera moves
+        let corners = batches.iter().flat_map(|batch| {
+            let corners = self.meshes.get_bounds(batch.mesh).get_corners();
+            batch.models.iter().flat_map(move |model| corners.map(|corner| light_view.transform_point(model.transform_point(corner))))
+        });
+        let bounds = Aabb::from_points(corners);
+
+        // Keeps flat scenes from collapsing the box and surfaces on its faces inside it
+        let padding = 0.01;
+        let projection = Mat4::orthographic(
+            bounds.min.x - padding, bounds.max.x + padding,
+            bounds.min.y - padding, bounds.max.y + padding,
+            bounds.min.z - padding, bounds.max.z + padding
+        );
+
+        let shadow = ShadowData {
+            light_matrix: projection * light_view,
+            shadow_matrix: Mat4::IDENTITY,
+            light,
+            resolution: self.shadows.resolution.max(1),
+            bias: self.shadows.bias,
+            pcf_radius: self.shadows.pcf_radius
+        };
+        shadow.for_view(Mat4::IDENTITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_direction(light: Option<(usize, Vec3)>, index: usize, direction: Vec3) {
+        let (light, actual) = light.unwrap();
+        assert_eq!(light, index);
+        assert!((actual - direction).length() < 1e-5, "{:?} != {:?}", actual, direction);
+    }
+
+    #[test]
+    fn point_lights_fall_back_to_the_global_light() {
+        let mut world = World::new("Shadows");
+        world.add_object(Light::point("Lamp", Vec3::Y));
+        world.set_global_light(Vec3::new(0.0, 3.0, 0.0), Vec3::ONE);
+        world.update_transforms();
+
+        // Rays travel away from the light
+        assert_direction(world.get_shadow_light(), 0, -Vec3::Y);
+
+        world.set_global_light(Vec3::Y, Vec3::ZERO);
+        assert_eq!(world.get_shadow_light(), None);
+    }
+
+    #[test]
+    fn the_global_light_casts_before_directional_lights() {
+        let mut world = World::new("Shadows");
+        world.add_object(Light::point("Lamp", Vec3::Y));
+        world.add_object(Light::directional("Sun", Vec3::X));
+        world.set_global_light(Vec3::Y, Vec3::ONE);
+        world.update_transforms();
+        assert_direction(world.get_shadow_light(), 0, -Vec3::Y);
+
+        // With the global light off the sun takes over
+        world.set_global_light(Vec3::Y, Vec3::ZERO);
+        assert_direction(world.get_shadow_light(), 1, Vec3::X);
+    }
+}
@@ -0,0 +1,289 @@
+// Material images, uploaded once per path
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use glium::backend::{Context, Facade};
+use glium::framebuffer::{DepthRenderBuffer, SimpleFrameBuffer};
+use glium::texture::{DepthFormat, MipmapsOption, RawImage2d, Texture2d, UncompressedFloatFormat};
+use glium::uniforms::{MagnifySamplerFilter, MinifySamplerFilter, SamplerBehavior, SamplerWrapFunction};
+use image::RgbaImage;
+use serde::{Serialize, Deserialize};
+
+use super::gltf_import;
+
+#[derive(Debug)]
+pub enum TextureError {
+    Image(PathBuf, image::ImageError),
+    // Image inside a glTF file that could not be read
+    Embedded(PathBuf, gltf_import::GltfError),
+    Upload(PathBuf, String)
+}
+
+impl fmt::Display for TextureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TextureError::Image(path, err) => write!(f, "can't load {}: {}", path.display(), err),
+            TextureError::Embedded(path, err) => write!(f, "can't load {}: {}", path.display(), err),
+            TextureError::Upload(path, err) => write!(f, "can't upload {}: {}", path.display(), err)
+        }
+    }
+}
+
+impl std::error::Error for TextureError {}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Filter {
+    Nearest,
+    Linear
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Wrap {
+    Repeat,
+    Mirror,
+    Clamp
+}
+
+// How a material's textures are sampled
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Sampler {
+    pub filter: Filter,
+    pub wrap: Wrap,
+    pub mipmaps: bool,
+    // 1 turns anisotropic filtering off
+    pub anisotropy: u16
+}
+
+impl Default for Sampler {
+    fn default() -> Self {
+        Self {filter: Filter::Linear, wrap: Wrap::Repeat, mipmaps: true, anisotropy: 4}
+    }
+}
+
+impl Sampler {
+    pub fn get_behavior(&self) -> SamplerBehavior {
+        let wrap = match self.wrap {
+            Wrap::Repeat => SamplerWrapFunction::Repeat,
+            Wrap::Mirror => SamplerWrapFunction::Mirror,
+            Wrap::Clamp => SamplerWrapFunction::Clamp
+        };
+
+        let (minify_filter, magnify_filter) = match (self.filter, self.mipmaps) {
+            (Filter::Nearest, false) => (MinifySamplerFilter::Nearest, MagnifySamplerFilter::Nearest),
+            (Filter::Nearest, true) => (MinifySamplerFilter::NearestMipmapNearest, MagnifySamplerFilter::Nearest),
+            (Filter::Linear, false) => (MinifySamplerFilter::Linear, MagnifySamplerFilter::Linear),
+            (Filter::Linear, true) => (MinifySamplerFilter::LinearMipmapLinear, MagnifySamplerFilter::Linear)
+        };
+
+        SamplerBehavior {
+            wrap_function: (wrap, wrap, wrap),
+            minify_filter,
+            magnify_filter,
+            max_anisotropy: self.anisotropy.max(1),
+            ..Default::default()
+        }
+    }
+}
+
+// PNG or JPEG as 8-bit RGBA
+pub fn load_image<P: AsRef<Path>>(path: P) -> Result<RgbaImage, TextureError> {
+    let path = path.as_ref();
+    let image = image::open(path).map_err(|err| TextureError::Image(path.to_path_buf(), err))?;
+    Ok(image.to_rgba8())
+}
+
+// glTF file an embedded image name (see gltf_import::image_name) points into
+pub fn embedded_file(name: &str) -> Option<&str> {
+    let (file, index) = name.rsplit_once("#image")?;
+    index.parse::<usize>().ok().map(|_| file)
+}
+
+// Color and depth a camera draws into, the color is then sampled like any loaded image
+pub struct RenderTarget {
+    context: Rc<Context>,
+    color: Texture2d,
+    depth: DepthRenderBuffer
+}
+
+impl RenderTarget {
+    pub fn new<F: Facade>(facade: &F, width: u32, height: u32) -> Result<Self, String> {
+        let context = facade.get_context().clone();
+        let color = Texture2d::empty_with_format(&context, UncompressedFloatFormat::U8U8U8U8, MipmapsOption::NoMipmap, width, height)
+            .map_err(|err| err.to_string())?;
+        let depth = DepthRenderBuffer::new(&context, DepthFormat::F32, width, height).map_err(|err| err.to_string())?;
+        Ok(Self {context, color, depth})
+    }
+
+    pub fn get_dimensions(&self) -> (u32, u32) {
+        self.color.dimensions()
+    }
+
+    pub fn get_color(&self) -> &Texture2d {
+        &self.color
+    }
+
+    pub fn get_framebuffer(&self) -> Result<SimpleFrameBuffer<'_>, String> {
+        SimpleFrameBuffer::with_depth_buffer(&self.context, &self.color, &self.depth).map_err(|err| format!("{:?}", err))
+    }
+}
+
+// Textures by path and whether they have mipmaps, and the render targets by name
+pub struct TextureCache {
+    context: Rc<Context>,
+    // None remembers a failed load so it is reported once instead of every frame
+    textures: HashMap<(String, bool), Option<Texture2d>>,
+    // Found by `get` before any file with the same path, None while a camera draws into it
+    targets: HashMap<String, Option<RenderTarget>>,
+    // Decoded images without a file of their own, used instead of reading the path
+    images: HashMap<String, RgbaImage>
+}
+
+impl TextureCache {
+    pub fn new<F: Facade>(facade: &F) -> Self {
+        Self {context: facade.get_context().clone(), textures: HashMap::new(), targets: HashMap::new(), images: HashMap::new()}
+    }
+
+    // Keeps the first image given for a name
+    pub fn add_image(&mut self, name: &str, image: &RgbaImage) {
+        if !self.images.contains_key(name) {
+            self.images.insert(name.to_string(), image.clone());
+        }
+    }
+
+    // Reads and uploads the image the first time the path is seen
+    pub fn load(&mut self, path: &str, mipmaps: bool) {
+        let key = (path.to_string(), mipmaps);
+        if self.textures.contains_key(&key) || self.is_target(path) {
+            return;
+        }
+
+        let texture = match self.upload(path, mipmaps) {
+            Ok(texture) => Some(texture),
+            Err(err) => {
+                println!("Can't load texture: {}", err);
+                None
+            }
+        };
+        self.textures.insert(key, texture);
+    }
+
+    fn upload(&mut self, path: &str, mipmaps: bool) -> Result<Texture2d, TextureError> {
+        // Embedded glTF images (see gltf_import::image_name) come from the file, which is read
+        // once for all of them. Only needed when the model was not added with `load_gltf`.
+        if let Some(file) = embedded_file(path).filter(|_| !self.images.contains_key(path)) {
+            let images = gltf_import::load_gltf_images(file).map_err(|err| TextureError::Embedded(PathBuf::from(path), err))?;
+            self.images.extend(images);
+        }
+
+        let image = match self.images.get(path) {
+            Some(image) => image.clone(),
+            None => load_image(path)?
+        };
+        let dimensions = image.dimensions();
+        // Images start at the top row, GL textures at the bottom one
+        let raw = RawImage2d::from_raw_rgba_reversed(&image.into_raw(), dimensions);
+
+        let mipmaps = match mipmaps {
+            true => MipmapsOption::AutoGeneratedMipmaps,
+            false => MipmapsOption::NoMipmap
+        };
+        Texture2d::with_mipmaps(&self.context, raw, mipmaps).map_err(|err| TextureError::Upload(PathBuf::from(path), err.to_string()))
+    }
+
+    // None if not loaded yet or the load failed
+    pub fn get(&self, path: &str, mipmaps: bool) -> Option<&Texture2d> {
+        if let Some(target) = self.targets.get(path) {
+            return target.as_ref().map(RenderTarget::get_color);
+        }
+        self.textures.get(&(path.to_string(), mipmaps))?.as_ref()
+    }
+
+    // Render targets have no mipmaps, samplers must not expect them
+    pub fn is_target(&self, path: &str) -> bool {
+        self.targets.contains_key(path)
+    }
+
+    // Takes the target out so it can be drawn into without sampling itself, (re)creating it at
+    // the given size. Until it is put back with `insert_target` materials see no texture.
+    pub fn take_target(&mut self, name: &str, width: u32, height: u32) -> Option<RenderTarget> {
+        let (width, height) = (width.max(1), height.max(1));
+        let taken = self.targets.entry(name.to_string()).or_default().take();
+        if let Some(target) = taken.filter(|target| target.get_dimensions() == (width, height)) {
+            return Some(target);
+        }
+
+        match RenderTarget::new(&self.context, width, height) {
+            Ok(target) => Some(target),
+            Err(err) => {
+                println!("Can't create render target {}: {}", name, err);
+                None
+            }
+        }
+    }
+
+    pub fn insert_target(&mut self, name: &str, target: RenderTarget) {
+        self.targets.insert(name.to_string(), Some(target));
+    }
+
+    pub fn clear(&mut self) {
+        self.textures.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.textures.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.textures.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> String {
+        format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+
+    #[test]
+    fn samplers_pick_the_filters() {
+        let pixelated = Sampler {filter: Filter::Nearest, wrap: Wrap::Clamp, mipmaps: false, anisotropy: 0}.get_behavior();
+        assert_eq!(pixelated.wrap_function, (SamplerWrapFunction::Clamp, SamplerWrapFunction::Clamp, SamplerWrapFunction::Clamp));
+        assert_eq!((pixelated.minify_filter, pixelated.magnify_filter), (MinifySamplerFilter::Nearest, MagnifySamplerFilter::Nearest));
+        // 0 would be invalid for GL, it means off like 1
+        assert_eq!(pixelated.max_anisotropy, 1);
+
+        let smooth = Sampler {wrap: Wrap::Mirror, ..Sampler::default()}.get_behavior();
+        assert_eq!(smooth.wrap_function.0, SamplerWrapFunction::Mirror);
+        assert_eq!((smooth.minify_filter, smooth.magnify_filter), (MinifySamplerFilter::LinearMipmapLinear, MagnifySamplerFilter::Linear));
+        assert_eq!(smooth.max_anisotropy, 4);
+
+        let nearest_mipmaps = Sampler {filter: Filter::Nearest, ..Sampler::default()}.get_behavior();
+        assert_eq!(nearest_mipmaps.minify_filter, MinifySamplerFilter::NearestMipmapNearest);
+    }
+
+    #[test]
+    fn broken_images_are_errors() {
+        assert!(matches!(load_image(fixture("missing.png")), Err(TextureError::Image(..))));
+        let corrupt = load_image(fixture("corrupt.png"));
+        assert!(matches!(&corrupt, Err(TextureError::Image(path, _)) if path.ends_with("corrupt.png")));
+    }
+
+    #[test]
+    fn embedded_names_lead_to_their_file() {
+        let path = fixture("embedded.glb");
+        let name = gltf_import::image_name(&path, 0);
+        assert_eq!(embedded_file(&name), Some(path.as_str()));
+        assert_eq!(embedded_file("textures/brick.png"), None);
+        assert_eq!(embedded_file("model.gltf#imagery.png"), None);
+
+        // What `upload` reads for a name it has no image for yet
+        let images = gltf_import::load_gltf_images(embedded_file(&name).unwrap()).unwrap();
+        let image = images.iter().find(|(image, _)| *image == name).map(|(_, image)| image);
+        assert_eq!(image.map(RgbaImage::dimensions), Some((2, 2)));
+    }
+}
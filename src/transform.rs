@@ -0,0 +1,147 @@
+use serde::{Serialize, Deserialize};
+
+use super::math::{Vec3, Mat4, Quat};
+
+// Translation, rotation and scale of an object, applied as T * R * S
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Transform {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Transform {
+    pub const IDENTITY: Self = Self {translation: Vec3::ZERO, rotation: Quat::IDENTITY, scale: Vec3::ONE};
+
+    pub fn new(translation: Vec3, rotation: Quat, scale: Vec3) -> Self {
+        Self {translation, rotation, scale}
+    }
+
+    pub fn from_translation(translation: Vec3) -> Self {
+        Self {translation, ..Self::IDENTITY}
+    }
+
+    pub fn from_rotation(rotation: Quat) -> Self {
+        Self {rotation, ..Self::IDENTITY}
+    }
+
+    pub fn from_scale(scale: Vec3) -> Self {
+        Self {scale, ..Self::IDENTITY}
+    }
+
+    pub fn get_matrix(&self) -> Mat4 {
+        Mat4::from_trs(self.translation, self.rotation, self.scale)
+    }
+
+    // Yaw, pitch and roll in radians
+    pub fn set_euler(&mut self, yaw: f32, pitch: f32, roll: f32) {
+        self.rotation = Quat::from_euler(yaw, pitch, roll);
+    }
+
+    pub fn translate(&mut self, offset: Vec3) {
+        self.translation += offset;
+    }
+
+    // Rotation applied on top of the current one, in world space
+    pub fn rotate(&mut self, rotation: Quat) {
+        self.rotation = (rotation * self.rotation).normalize();
+    }
+
+    // Turns the forward axis (+Z) towards `direction`
+    pub fn look_to(&mut self, direction: Vec3) {
+        self.rotation = Quat::from_rotation_arc(Vec3::Z, direction);
+    }
+
+    pub fn forward(&self) -> Vec3 {
+        self.rotation * Vec3::Z
+    }
+
+    pub fn right(&self) -> Vec3 {
+        self.rotation * Vec3::X
+    }
+
+    pub fn up(&self) -> Vec3 {
+        self.rotation * Vec3::Y
+    }
+
+    pub fn transform_point(&self, point: Vec3) -> Vec3 {
+        self.translation + self.rotation * (self.scale * point)
+    }
+
+    // Parent * child, exact as long as the parent has uniform scale
+    pub fn mul_transform(&self, child: &Transform) -> Self {
+        Self {
+            translation: self.transform_point(child.translation),
+            rotation: (self.rotation * child.rotation).normalize(),
+            scale: self.scale * child.scale
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+
+    use super::*;
+
+    const EPSILON: f32 = 1e-5;
+
+    fn assert_vec3(actual: Vec3, expected: Vec3) {
+        assert!(actual.approx_eq(expected, EPSILON), "{:?} != {:?}", actual, expected);
+    }
+
+    #[test]
+    fn rotation_turns_the_axes() {
+        // A quarter yaw turns forward (+Z) to +X and right (+X) to -Z
+        let mut transform = Transform::IDENTITY;
+        transform.set_euler(FRAC_PI_2, 0.0, 0.0);
+        assert_vec3(transform.forward(), Vec3::X);
+        assert_vec3(transform.right(), -Vec3::Z);
+        assert_vec3(transform.up(), Vec3::Y);
+
+        transform.look_to(Vec3::new(0.0, 0.0, -3.0));
+        assert_vec3(transform.forward(), -Vec3::Z);
+        transform.look_to(Vec3::Y);
+        assert_vec3(transform.forward(), Vec3::Y);
+
+        // Applied on top, so two quarter turns face backwards
+        let mut transform = Transform::from_rotation(Quat::from_axis_angle(Vec3::Y, FRAC_PI_2));
+        transform.rotate(Quat::from_axis_angle(Vec3::Y, FRAC_PI_2));
+        assert_vec3(transform.forward(), -Vec3::Z);
+        assert!((transform.rotation.length() - 1.0).abs() <= EPSILON);
+    }
+
+    #[test]
+    fn matrix_scales_then_rotates_then_translates() {
+        let transform = Transform::new(Vec3::new(1.0, 2.0, 3.0), Quat::from_axis_angle(Vec3::Y, FRAC_PI_2), Vec3::splat(2.0));
+        let point = Vec3::new(1.0, 0.0, 0.0);
+        // Scaled to (2, 0, 0), turned to (0, 0, -2), then moved
+        let expected = Vec3::new(1.0, 2.0, 1.0);
+        assert_vec3(transform.transform_point(point), expected);
+        assert_vec3(transform.get_matrix().transform_point(point), expected);
+        assert_vec3(Transform::IDENTITY.get_matrix().transform_point(point), point);
+    }
+
+    #[test]
+    fn composition_matches_the_matrix_product() {
+        let parent = Transform::new(Vec3::new(0.0, 1.0, 0.0), Quat::from_euler(0.3, -0.2, 0.1), Vec3::splat(2.0));
+        let child = Transform::new(Vec3::new(1.0, 0.0, -2.0), Quat::from_euler(-1.0, 0.5, 0.0), Vec3::new(1.0, 3.0, 0.5));
+        let combined = parent.mul_transform(&child);
+        let product = parent.get_matrix() * child.get_matrix();
+
+        for point in [Vec3::ZERO, Vec3::X, Vec3::new(-1.0, 2.0, 0.5)] {
+            assert_vec3(combined.transform_point(point), product.transform_point(point));
+            assert_vec3(combined.get_matrix().transform_point(point), product.transform_point(point));
+        }
+
+        let mut moved = child;
+        moved.translate(Vec3::new(0.0, -1.0, 0.0));
+        assert_vec3(moved.translation, Vec3::new(1.0, -1.0, -2.0));
+    }
+}
@@ -0,0 +1,281 @@
+// Cameras placed in the world like any other object. Each one draws the world into a part of the
+// window (split-screen, minimaps) or into a texture that materials sample (monitors, mirrors).
+use glium::uniforms::MagnifySamplerFilter;
+use glium::{BlitTarget, Rect, Surface};
+use serde::{Serialize, Deserialize};
+
+use super::math::{Mat4, Vec3};
+use super::renderer::{Batch, Renderer};
+use super::shadow::ShadowData;
+use super::{Camera, Object, Transform, World};
+
+// Part of the window in fractions of its size, measured from the top-left corner
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Viewport {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32
+}
+
+impl Default for Viewport {
+    fn default() -> Self {
+        Self::FULL
+    }
+}
+
+impl Viewport {
+    pub const FULL: Self = Self {x: 0.0, y: 0.0, width: 1.0, height: 1.0};
+
+    pub fn new(x: f32, y: f32, width: f32, height: f32) -> Self {
+        Self {x, y, width, height}
+    }
+
+    // Pixels covered in a target of the given size, clipped to it
+    pub fn get_rect(&self, (width, height): (u32, u32)) -> Rect {
+        let x = |fraction: f32| (fraction.clamp(0.0, 1.0) * width as f32).round() as u32;
+        let y = |fraction: f32| (fraction.clamp(0.0, 1.0) * height as f32).round() as u32;
+        let (left, right) = (x(self.x), x(self.x + self.width));
+        let (top, bottom) = (y(self.y), y(self.y + self.height));
+
+        // GL counts rows from the bottom
+        Rect {left, bottom: height - bottom, width: right.saturating_sub(left), height: bottom.saturating_sub(top)}
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ViewTarget {
+    // Drawn over the main camera's view
+    Window(Viewport),
+    // Materials show it by using `name` as a texture path
+    Texture {name: String, width: u32, height: u32}
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SceneCamera {
+    name: String,
+
+    pub transform: Transform,
+    // Lens of the view: projection, field of view and clip planes. Its position and
+    // direction are replaced by the node's world transform, which has no roll.
+    pub camera: Camera,
+    pub target: ViewTarget,
+    pub active: bool,
+    // Views on the window are drawn in ascending order, later ones on top
+    pub order: i32
+}
+
+impl Default for SceneCamera {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            transform: Transform::IDENTITY,
+            camera: Camera::new(),
+            target: ViewTarget::Window(Viewport::FULL),
+            active: true,
+            order: 0
+        }
+    }
+}
+
+impl SceneCamera {
+    pub fn viewport(name: &str, viewport: Viewport) -> Self {
+        Self {target: ViewTarget::Window(viewport), ..Self::new(name)}
+    }
+
+    pub fn texture(name: &str, texture: &str, width: u32, height: u32) -> Self {
+        Self {target: ViewTarget::Texture {name: texture.to_string(), width, height}, ..Self::new(name)}
+    }
+
+    // The lens moved to where the node is, looking along its forward axis
+    pub fn get_view_camera(&self, world_matrix: Mat4) -> Camera {
+        let mut camera = self.camera.clone();
+        camera.position = world_matrix.transform_point(Vec3::ZERO);
+        camera.set_direction(world_matrix.transform_vector(Vec3::Z));
+        camera
+    }
+}
+
+#[typetag::serde]
+impl Object for SceneCamera {
+    fn new(name: &str) -> Self where Self: Sized {
+        let name = name.to_string();
+        Self {name, ..Default::default()}
+    }
+
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn transform(&self) -> &Transform {
+        &self.transform
+    }
+
+    fn transform_mut(&mut self) -> &mut Transform {
+        &mut self.transform
+    }
+}
+
+impl World {
+    // Part of the window the main camera draws into
+    pub fn set_viewport(&mut self, viewport: Viewport) {
+        self.viewport = viewport;
+    }
+
+    pub fn get_viewport(&self) -> Viewport {
+        self.viewport
+    }
+
+    // Active scene cameras in draw order, as of the last `update_transforms`
+    pub fn collect_views(&self) -> Vec<(Camera, ViewTarget)> {
+        let mut views: Vec<(i32, Camera, ViewTarget)> = self.get_nodes().filter_map(|(_, node)| {
+            let camera = node.get_object().as_any().downcast_ref::<SceneCamera>()?;
+            camera.active.then(|| (camera.order, camera.get_view_camera(node.get_world_matrix()), camera.target.clone()))
+        }).collect();
+
+        // Stable, so equal orders keep slot order
+        views.sort_by_key(|(order, _, _)| *order);
+        views.into_iter().map(|(_, camera, target)| (camera, target)).collect()
+    }
+
+    // Draws every view of the world. Texture cameras go first so the other views show this
+    // frame's picture, then the main camera with the debug gizmos, then the window cameras.
+    pub fn draw<S: Surface>(&mut self, frame: &mut S, renderer: &mut Renderer) {
+        self.update_transforms();
+        renderer.add_images(&self.images);
+        let views = self.collect_views();
+
+        // One shadow map for every view, rendered before any of them samples it
+        let batches = self.collect_batches();
+        let shadow = self.get_shadow_data(&batches);
+        renderer.draw_shadows(&batches, &self.meshes, shadow);
+
+        for (camera, target) in &views {
+            let ViewTarget::Texture {name, width, height} = target else {
+                continue;
+            };
+            let Some(target) = renderer.take_target(name, *width, *height) else {
+                continue;
+            };
+            match target.get_framebuffer() {
+                Ok(mut framebuffer) => self.draw_view(&mut framebuffer, renderer, camera, None, &batches, shadow),
+                Err(err) => println!("Can't draw into {}: {}", name, err)
+            }
+            renderer.insert_target(name, target);
+        }
+
+        // Parts outside the main view would keep the last frame
+        if self.viewport != Viewport::FULL {
+            self.clear(frame, None);
+        }
+
+        let dimensions = frame.get_dimensions();
+        let main_rect = Some(self.viewport.get_rect(dimensions));
+        let camera = self.camera.clone();
+        self.draw_view(frame, renderer, &camera, main_rect, &batches, shadow);
+        self.draw_axis(frame, renderer, main_rect);
+
+        for (camera, target) in &views {
+            if let ViewTarget::Window(viewport) = target {
+                self.draw_view(frame, renderer, camera, Some(viewport.get_rect(dimensions)), &batches, shadow);
+            }
+        }
+    }
+
+    // `draw` for the window. Its depth buffer is fixed point, where reverse-Z gains nothing, so a
+    // reverse-Z main camera draws every view into a float depth target that is then copied over.
+    pub fn draw_window<S: Surface>(&mut self, frame: &mut S, renderer: &mut Renderer) {
+        let (width, height) = frame.get_dimensions();
+        let target = match self.camera.is_reverse_z() {
+            true => renderer.take_window_target(width, height),
+            false => None
+        };
+        let Some(target) = target else {
+            return self.draw(frame, renderer);
+        };
+
+        match target.get_framebuffer() {
+            Ok(mut framebuffer) => {
+                self.draw(&mut framebuffer, renderer);
+                let rect = BlitTarget {left: 0, bottom: 0, width: width as i32, height: height as i32};
+                framebuffer.blit_whole_color_to(frame, &rect, MagnifySamplerFilter::Nearest);
+            },
+            Err(err) => {
+                println!("Can't draw into the window target: {}", err);
+                self.draw(frame, renderer);
+            }
+        }
+        renderer.insert_window_target(target);
+    }
+
+    // Clears the rectangle and draws the objects in it as seen by `camera`
+    fn draw_view<S: Surface>(&mut self, frame: &mut S, renderer: &mut Renderer, camera: &Camera, rect: Option<Rect>, batches: &[Batch], shadow: Option<ShadowData>) {
+        if rect.is_some_and(|rect| rect.width == 0 || rect.height == 0) {
+            return;
+        }
+
+        let main_camera = std::mem::replace(&mut self.camera, camera.clone());
+        self.clear(frame, rect);
+        self.draw_objects(frame, renderer, rect, batches, shadow);
+        self.camera = main_camera;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::headless::test_renderer;
+    use super::super::{Cuboid, Teapot};
+
+    #[test]
+    fn viewport_rects_count_rows_from_the_bottom() {
+        let top_right = Viewport::new(0.75, 0.0, 0.25, 0.25).get_rect((800, 600));
+        assert_eq!(top_right, Rect {left: 600, bottom: 450, width: 200, height: 150});
+        assert_eq!(Viewport::FULL.get_rect((800, 600)), Rect {left: 0, bottom: 0, width: 800, height: 600});
+        // Clipped to the target
+        let outside = Viewport::new(0.5, 0.5, 1.0, 1.0).get_rect((100, 100));
+        assert_eq!(outside, Rect {left: 50, bottom: 0, width: 50, height: 50});
+    }
+
+    #[test]
+    fn views_come_in_order() {
+        let mut world = World::new("Views");
+        assert_eq!(world.get_viewport(), Viewport::FULL);
+        world.set_viewport(Viewport::new(0.0, 0.0, 0.5, 1.0));
+        assert_eq!(world.get_viewport().width, 0.5);
+
+        let mut minimap = SceneCamera::viewport("Minimap", Viewport::new(0.75, 0.0, 0.25, 0.25));
+        minimap.order = 2;
+        minimap.transform.translation = Vec3::new(0.0, 10.0, 0.0);
+        world.add_object(minimap);
+        let mut monitor = SceneCamera::texture("Monitor Camera", "monitor", 64, 64);
+        monitor.order = 1;
+        world.add_object(monitor);
+        let mut off = SceneCamera::new("Off");
+        off.active = false;
+        world.add_object(off);
+
+        world.update_transforms();
+        let views = world.collect_views();
+        assert_eq!(views.len(), 2);
+        assert!(matches!(&views[0].1, ViewTarget::Texture {name, ..} if name == "monitor"));
+        assert!(views[1].0.position.approx_eq(Vec3::new(0.0, 10.0, 0.0), 1e-6));
+    }
+
+    #[test]
+    fn shadows_render_once_for_every_view() {
+        let Some(mut headless) = test_renderer("shadows_render_once_for_every_view") else {
+            return;
+        };
+
+        let mut world = World::new("Views");
+        world.add_object(Cuboid::new("Floor"));
+        world.add_object(Teapot::new("Teapot"));
+        world.add_object(SceneCamera::viewport("Minimap", Viewport::new(0.75, 0.0, 0.25, 0.25)));
+        world.add_object(SceneCamera::texture("Monitor Camera", "monitor", 16, 16));
+
+        let camera = world.camera.clone();
+        headless.render(&mut world, &camera, 32, 32).unwrap();
+        assert_eq!(headless.renderer.get_stats().shadow_casters, 2);
+    }
+}